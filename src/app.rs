@@ -0,0 +1,453 @@
+use std::{
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
+
+use freetype::Library;
+use objc2::rc::autoreleasepool;
+use objc2_app_kit::{
+    NSAlert, NSAlertFirstButtonReturn, NSAlertSecondButtonReturn, NSAnyEventMask,
+    NSEventModifierFlags, NSEventType, NSWindow,
+};
+use objc2_foundation::{
+    MainThreadMarker, NSComparisonResult, NSDate, NSDefaultRunLoopMode, NSString,
+};
+use txt_rs::{
+    atlas::Atlas,
+    buffer::TextBuffer,
+    cli::Args,
+    command::Modifiers,
+    config::Config,
+    document::{CloseChoice, ClosePrompt, ConflictChoice, DiskChange, Document},
+    editor::{Editor, InputEvent},
+    file_format::FileFormat,
+    keymap::{Key, Keymap, Preset},
+    maths::{Float2, Float4},
+    recovery::Recovery,
+    render::Renderer,
+    text::{FontChain, loader::FontLoader},
+};
+
+use crate::{
+    metal_renderer::MetalRenderer,
+    pasteboard::Pasteboard,
+    utils::{get_next_frame, simple_app},
+};
+
+//how often the files are checked for changes made by other programs
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn run(args: Args) {
+    let view_width = 1024.0;
+    let view_height = 768.0;
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("{err}, using the default config");
+        Config::default()
+    });
+    for invalid in &config.keymap.invalid_bindings {
+        eprintln!("ignoring key binding {invalid}");
+    }
+    let font_size = config.font.size;
+
+    let paths = match args.paths.is_empty() {
+        true => vec![PathBuf::from("text.txt")],
+        false => args.paths,
+    };
+    let loaded: Vec<(TextBuffer, FileFormat)> = paths
+        .iter()
+        .map(|path| {
+            Document::load(path).unwrap_or_else(|err| {
+                eprintln!("couldn't open {}: {err}", path.display());
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let (app, window, device, layer) = simple_app(view_width, view_height, "Texter");
+    //closing only hides the window, it comes back if the unsaved changes prompt is cancelled
+    unsafe { window.setReleasedWhenClosed(false) };
+    let mut renderer =
+        MetalRenderer::new(device, layer, Float2(view_width as f32, view_height as f32));
+
+    let ft_lib = Library::init().unwrap();
+    let mut font_loader = FontLoader::new(&ft_lib);
+    let text_box_size = 2000.0;
+    let color = Float4(0.0, 0.0, 0.0, 1.0);
+    let cursor_start = Float2(-1000.0, 700.0);
+    let meta_option = config.keymap.preset == Preset::Emacs;
+
+    //one per file on the command line, cmd+shift+[ and ] switch between them
+    let mut documents: Vec<Document> = paths
+        .into_iter()
+        .zip(loaded)
+        .map(|(path, (text_buffer, format))| {
+            //every editor owns its atlas, so each gets its own copy of the fonts
+            let font = font_loader
+                .load(&config.font.primary, font_size)
+                .unwrap_or_else(|err| panic!("{err}"));
+            let atlas = Atlas::new(&font.face).unwrap();
+            let mut fonts = FontChain::new(font);
+            for source in &config.font.fallbacks {
+                match font_loader.load(source, font_size) {
+                    Ok(fallback) => fonts.push(fallback),
+                    Err(err) => eprintln!("skipping fallback font: {err}"),
+                }
+            }
+
+            let mut editor = Editor::new(
+                text_buffer,
+                atlas,
+                fonts,
+                font_size,
+                cursor_start,
+                text_box_size,
+                color,
+            );
+            editor.set_clipboard(Box::new(Pasteboard::general()));
+            let mut keymap = Keymap::preset(config.keymap.preset);
+            for (chord, binding) in &config.keymap.bindings {
+                keymap.bind(*chord, *binding);
+            }
+            editor.set_keymap(keymap);
+            editor.set_vim_enabled(config.keymap.vim);
+            editor.set_readonly(args.readonly);
+            //the shader maps -height..height onto the window
+            editor.set_page_height(view_height as f32 * 2.0);
+            if format.mixed_line_endings {
+                eprintln!(
+                    "{} has mixed line endings, saving makes them all {}",
+                    path.display(),
+                    format.line_ending
+                );
+            }
+            let mut document = Document::new(path, editor, format);
+            //converting shows as an unsaved change until the next save
+            document.set_format(format.convert(args.line_ending, args.encoding, args.bom));
+            document
+        })
+        .collect();
+    if args.line.is_some() || args.column.is_some() {
+        let line = args.line.unwrap_or(1) - 1;
+        let column = args.column.unwrap_or(1) - 1;
+        documents[0].editor_mut().go_to(line, column);
+    }
+    //whatever a crashed session left behind is offered before anything gets journaled over it
+    if let Some(dir) = Recovery::dir() {
+        for document in documents
+            .iter_mut()
+            .filter(|document| !document.editor().is_readonly())
+        {
+            document.set_recovery_dir(&dir);
+            let restored = match document.recovered_text() {
+                Ok(Some(text)) if ask_to_restore(document) => document.restore(&text),
+                Ok(Some(_)) => document.discard_recovery(),
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = restored {
+                eprintln!("couldn't recover {}: {err}", document.path().display());
+            }
+        }
+    }
+
+    let mut current = 0;
+    show_document(&documents[current], &window, &mut renderer);
+
+    let fps = 60.0f32;
+    let mut frames = 0;
+    let mut frame_time = get_next_frame(fps as f64);
+    let mut disk_checked = Instant::now();
+
+    loop {
+        autoreleasepool(|_| {
+            if !window.isVisible() {
+                let can_close = match ClosePrompt::new(&documents) {
+                    Some(prompt) => {
+                        let choice = ask_to_save(&prompt);
+                        prompt
+                            .resolve(&mut documents, choice)
+                            .unwrap_or_else(|err| {
+                                eprintln!("{err}");
+                                false
+                            })
+                    }
+                    None => true,
+                };
+                match can_close {
+                    true => unsafe { app.terminate(None) },
+                    false => {
+                        show_document(&documents[current], &window, &mut renderer);
+                        window.makeKeyAndOrderFront(None);
+                    }
+                }
+            }
+            for document in &mut documents {
+                if let Err(err) = document.autosave() {
+                    eprintln!("couldn't autosave {}: {err}", document.path().display());
+                }
+                if let Some(err) = document.take_recovery_error() {
+                    eprintln!(
+                        "couldn't keep unsaved changes to {} for recovery: {err}",
+                        document.path().display()
+                    );
+                }
+            }
+            if disk_checked.elapsed() >= DISK_CHECK_INTERVAL {
+                disk_checked = Instant::now();
+                for (index, document) in documents.iter_mut().enumerate() {
+                    let changed = document.check_disk().and_then(|change| match change {
+                        DiskChange::Conflict => {
+                            let choice = ask_about_conflict(document);
+                            document.resolve_conflict(choice).map(|_| true)
+                        }
+                        DiskChange::LargeFileChanged => match ask_to_reload(document) {
+                            true => document.reload_from_disk().map(|_| true),
+                            false => Ok(false),
+                        },
+                        DiskChange::Reloaded | DiskChange::Deleted => Ok(true),
+                        DiskChange::Unchanged => Ok(false),
+                    });
+                    match changed {
+                        Ok(true) if index == current => {
+                            show_document(document, &window, &mut renderer)
+                        }
+                        Ok(_) => {}
+                        Err(err) => {
+                            eprintln!("couldn't reload {}: {err}", document.path().display())
+                        }
+                    }
+                }
+            }
+            let editor = documents[current].editor_mut();
+            if unsafe { frame_time.compare(&NSDate::now()) } == NSComparisonResult::Ascending {
+                frame_time = get_next_frame(fps as f64);
+                frames += 1;
+
+                //pick up any glyphs packed or evicted by the last layout
+                renderer.upload_atlas(editor.atlas());
+                renderer.draw_rects(&editor.selection_verts());
+                renderer.draw_glyphs();
+                renderer.draw_rects(&editor.cursor_verts());
+                renderer.present();
+                // println!("{:?}", cursor);
+                std::thread::sleep(Duration::from_millis((1000.0 / fps) as u64));
+            }
+
+            loop {
+                unsafe {
+                    let e = app.nextEventMatchingMask_untilDate_inMode_dequeue(
+                        NSAnyEventMask,
+                        None,
+                        NSDefaultRunLoopMode,
+                        true,
+                    );
+                    match e {
+                        Some(ref e) => match e.r#type() {
+                            NSEventType::KeyDown => {
+                                let modifiers = modifiers_from_flags(e.modifierFlags());
+                                //ctrl turns letters into control chars and option into symbols,
+                                //the keymap wants the key, option only types when it isn't meta
+                                let option_as_meta = modifiers.option && meta_option;
+                                let in_chars = match modifiers.control
+                                    || modifiers.command
+                                    || option_as_meta
+                                {
+                                    true => e.charactersIgnoringModifiers(),
+                                    false => e.characters(),
+                                };
+                                let Some(in_chars) = in_chars.map(|str| str.to_string()) else {
+                                    //e.g. dead keys mid composition, nothing to do
+                                    continue;
+                                };
+                                if modifiers.command && modifiers.shift {
+                                    let step = match in_chars.as_str() {
+                                        "}" | "]" => Some(1),
+                                        "{" | "[" => Some(documents.len() - 1),
+                                        _ => None,
+                                    };
+                                    if let Some(step) = step {
+                                        current = (current + step) % documents.len();
+                                        show_document(&documents[current], &window, &mut renderer);
+                                        continue;
+                                    }
+                                }
+                                let document = &mut documents[current];
+                                //should try not to block the event loop, for now it's fine
+                                //a single key can produce several chars, e.g. surrogate pairs
+                                for char in in_chars.chars() {
+                                    let event =
+                                        InputEvent::KeyDown(key_from_appkit(char), modifiers);
+                                    match document.handle_input(event) {
+                                        Ok(true) => {
+                                            let editor = document.editor();
+                                            renderer.upload_glyph_instances(
+                                                editor.text_verts(),
+                                                editor.tex_pointers(),
+                                                editor.page_ranges(),
+                                            );
+                                        }
+                                        Ok(false) => {}
+                                        Err(err) => {
+                                            eprintln!("{}: {err}", document.path().display())
+                                        }
+                                    }
+                                }
+                                //edits and saves change the edited marker
+                                set_title(document, &window);
+                            }
+                            _ => app.sendEvent(e),
+                        },
+                        None => {
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+//uploads the document's glyphs and names the window after it
+fn show_document(document: &Document, window: &NSWindow, renderer: &mut MetalRenderer) {
+    set_title(document, window);
+    let editor = document.editor();
+    renderer.upload_glyph_instances(
+        editor.text_verts(),
+        editor.tex_pointers(),
+        editor.page_ranges(),
+    );
+}
+
+fn set_title(document: &Document, window: &NSWindow) {
+    let title = document.title();
+    if window.title().to_string() != title {
+        window.setTitle(&NSString::from_str(&title));
+    }
+}
+
+//true to restore the unsaved changes, false to open the file as it is on disk
+fn ask_to_restore(document: &Document) -> bool {
+    let mtm = MainThreadMarker::new().expect("Not running on main thread");
+    let message = format!(
+        "{} has unsaved changes from a session that didn't quit properly.",
+        document.path().display()
+    );
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setMessageText(&NSString::from_str(&message));
+        alert.setInformativeText(&NSString::from_str(
+            "Restore them to carry on where you left off, or discard them to open the file as saved.",
+        ));
+        alert.addButtonWithTitle(&NSString::from_str("Restore"));
+        alert.addButtonWithTitle(&NSString::from_str("Discard"));
+        alert.runModal() == NSAlertFirstButtonReturn
+    }
+}
+
+fn ask_about_conflict(document: &Document) -> ConflictChoice {
+    let mtm = MainThreadMarker::new().expect("Not running on main thread");
+    let message = format!(
+        "{} was changed by another program while it had unsaved changes.",
+        document.path().display()
+    );
+    //large files keep no copy of the text to merge against
+    let (choices, detail) = match document.can_merge() {
+        true => (
+            &[
+                ("Merge", ConflictChoice::Merge),
+                ("Keep Mine", ConflictChoice::KeepMine),
+                ("Reload", ConflictChoice::Reload),
+            ][..],
+            "Merge combines both, marking lines changed on both sides with <<<<<<< and >>>>>>>. \
+             Keep Mine overwrites the other changes when saved. Reload throws away yours.",
+        ),
+        false => (
+            &[
+                ("Keep Mine", ConflictChoice::KeepMine),
+                ("Reload", ConflictChoice::Reload),
+            ][..],
+            "Keep Mine overwrites the other changes when saved. Reload throws away yours.",
+        ),
+    };
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setMessageText(&NSString::from_str(&message));
+        alert.setInformativeText(&NSString::from_str(detail));
+        for (title, _) in choices {
+            alert.addButtonWithTitle(&NSString::from_str(title));
+        }
+        let response = alert.runModal() - NSAlertFirstButtonReturn;
+        choices
+            .get(response as usize)
+            .map_or(ConflictChoice::KeepMine, |&(_, choice)| choice)
+    }
+}
+
+//true for Reload, large files are only read again if asked to
+fn ask_to_reload(document: &Document) -> bool {
+    let mtm = MainThreadMarker::new().expect("Not running on main thread");
+    let message = format!(
+        "{} was changed by another program.",
+        document.path().display()
+    );
+    let detail = "It's too large to reload automatically. Reload reads it again, throwing away \
+                  any unsaved changes. Ignore keeps what's open, and saving overwrites the file.";
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setMessageText(&NSString::from_str(&message));
+        alert.setInformativeText(&NSString::from_str(detail));
+        alert.addButtonWithTitle(&NSString::from_str("Reload"));
+        alert.addButtonWithTitle(&NSString::from_str("Ignore"));
+        alert.runModal() == NSAlertFirstButtonReturn
+    }
+}
+
+//blocks until one of the buttons is clicked
+fn ask_to_save(prompt: &ClosePrompt) -> CloseChoice {
+    let mtm = MainThreadMarker::new().expect("Not running on main thread");
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setMessageText(&NSString::from_str(&prompt.message));
+        alert.setInformativeText(&NSString::from_str(&prompt.detail));
+        alert.addButtonWithTitle(&NSString::from_str("Save"));
+        alert.addButtonWithTitle(&NSString::from_str("Don't Save"));
+        alert.addButtonWithTitle(&NSString::from_str("Cancel"));
+        match alert.runModal() {
+            response if response == NSAlertFirstButtonReturn => CloseChoice::Save,
+            response if response == NSAlertSecondButtonReturn => CloseChoice::Discard,
+            _ => CloseChoice::Cancel,
+        }
+    }
+}
+
+//AppKit reports arrows and other function keys as chars in a private use range,
+//the editor only ever sees the decoded key
+fn key_from_appkit(char: char) -> Key {
+    match char {
+        '\u{F700}' => Key::Up,
+        '\u{F701}' => Key::Down,
+        '\u{F702}' => Key::Left,
+        '\u{F703}' => Key::Right,
+        '\u{F704}'..='\u{F726}' => Key::F((char as u32 - 0xF704 + 1) as u8),
+        '\u{F728}' => Key::Delete,
+        '\u{F729}' => Key::Home,
+        '\u{F72B}' => Key::End,
+        '\u{F72C}' => Key::PageUp,
+        '\u{F72D}' => Key::PageDown,
+        '\u{F700}'..='\u{F8FF}' => Key::Other(char),
+        //keypad enter and shift+tab
+        '\u{3}' => Key::Enter,
+        '\u{19}' => Key::Tab,
+        _ => Key::from(char),
+    }
+}
+
+fn modifiers_from_flags(flags: NSEventModifierFlags) -> Modifiers {
+    Modifiers {
+        shift: flags.contains(NSEventModifierFlags::NSEventModifierFlagShift),
+        control: flags.contains(NSEventModifierFlags::NSEventModifierFlagControl),
+        option: flags.contains(NSEventModifierFlags::NSEventModifierFlagOption),
+        command: flags.contains(NSEventModifierFlags::NSEventModifierFlagCommand),
+    }
+}
@@ -1,85 +1,285 @@
-use freetype::{
-    Face, FtResult,
-    ffi::{FT_BBox, FT_Vector},
-};
-use metal::*;
+use std::collections::HashMap;
+
+use freetype::{Face, FtResult};
 
-use crate::{maths::Float2, text::freetype::get_char_glyph};
+use crate::{
+    packer::{Allocation, ShelfPacker},
+    text::freetype::{GlyphData, get_glyph},
+};
 
+//printable ascii is rasterized up front, everything else on first use
 pub const ASCII_START: u8 = 32;
 pub const NUM_ASCII_CHARS: u8 = 96;
 
+//well under the 16384 max mtltexture size, more pages get added instead of growing one
+pub const PAGE_SIZE: u32 = 2048;
+//past this many pages least recently used glyphs get evicted to make room
+pub const MAX_PAGES: usize = 4;
+
+//glyph ids are only unique within a font, so the font's index in the chain is part of the key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    pub font: usize,
+    pub glyph_id: u32,
+}
+
+pub struct CachedGlyph {
+    pub data: GlyphData,
+    pub page: usize,
+    pub allocation: Allocation,
+    last_used: u64,
+}
+
+impl CachedGlyph {
+    pub fn get_advance(&self) -> f32 {
+        self.data.advance.x as f32 / 64.0
+    }
+
+    //top left of the glyph's rect in its page, in pixels
+    pub fn tex_pointer(&self) -> (u32, u32) {
+        (self.allocation.x, self.allocation.y)
+    }
+}
+
+pub struct AtlasPage {
+    pub packer: ShelfPacker,
+    generation: u64,
+}
+
+impl AtlasPage {
+    //bumped whenever a glyph is added to or evicted from the page
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+//cpu side glyph cache, the renderer uploads each page into its own texture
 pub struct Atlas {
-    pub texture: Texture,
-    pub max_width: u64,
-    pub max_height: u64,
-    // bitmaps: Vec<Vec<u8>>,
-    pub advances: Vec<FT_Vector>,
-    pub cboxes: Vec<FT_BBox>,
+    pub line_height: f32,
+    pages: Vec<AtlasPage>,
+    glyphs: HashMap<GlyphKey, CachedGlyph>,
+    //glyphs used since the last begin_layout are pinned and never evicted
+    layout_count: u64,
 }
 
-//current texture atlas impl doesn't work for large fonts, max mtltexture size is 16384
 impl Atlas {
-    pub fn new(face: &Face, device: &DeviceRef) -> FtResult<Atlas> {
-        let mut all_char_bitmaps = Vec::new();
-        let mut all_char_widths = Vec::new();
-        let mut all_char_heights = Vec::new();
-        let mut all_char_advances = Vec::new();
-        let mut all_char_cboxes = Vec::new();
-
-        let mut max_width = 0;
-        let mut max_height = 0;
+    //primary is the first font in the chain, its line height is used for every line
+    pub fn new(primary: &Face) -> FtResult<Atlas> {
+        let line_height = primary
+            .size_metrics()
+            .map(|metrics| metrics.height as f32 / 64.0)
+            .unwrap_or_default();
+        let mut atlas = Atlas {
+            line_height,
+            pages: Vec::new(),
+            glyphs: HashMap::new(),
+            layout_count: 0,
+        };
         for i in ASCII_START..ASCII_START + NUM_ASCII_CHARS {
             // println!("{i}");
-            let glyph_data = get_char_glyph(face, i.into())?;
-            max_width = max_width.max(glyph_data.width);
-            max_height = max_height.max(glyph_data.height);
-            all_char_bitmaps.push(glyph_data.bitmap);
-            all_char_widths.push(glyph_data.width);
-            all_char_heights.push(glyph_data.height);
-            all_char_advances.push(glyph_data.advance);
-            all_char_cboxes.push(glyph_data.cbox);
+            let glyph_id = primary.get_char_index(i as usize).unwrap_or_default();
+            atlas.glyph(primary, GlyphKey { font: 0, glyph_id })?;
         }
-        let atlas_descriptor = TextureDescriptor::new();
-        let padded_height = max_height + 1;
-        let tex_height = (NUM_ASCII_CHARS as u64) * padded_height;
-        atlas_descriptor.set_pixel_format(MTLPixelFormat::R8Unorm);
-        atlas_descriptor.set_width(max_width);
-        atlas_descriptor.set_height(tex_height);
-
-        let texture = device.new_texture(&atlas_descriptor);
-
-        for i in 0..NUM_ASCII_CHARS as usize {
-            let height_diff = max_height - all_char_heights[i];
-            let region = MTLRegion::new_2d(
-                0,
-                padded_height * i as u64 + height_diff, //if char height = max height the replaced region has 1 row of padding
-                all_char_widths[i],
-                all_char_heights[i],
-            );
-            if all_char_widths[i] <= 0 || all_char_heights[i] <= 0 {
+        Ok(atlas)
+    }
+
+    //call before laying out a frame's worth of text
+    pub fn begin_layout(&mut self) {
+        self.layout_count += 1;
+    }
+
+    //looks up the glyph, rasterizing and packing it if it hasn't been seen yet
+    //face has to be the font the key's index refers to
+    pub fn glyph(&mut self, face: &Face, key: GlyphKey) -> FtResult<&CachedGlyph> {
+        if !self.glyphs.contains_key(&key) {
+            let glyph_data = get_glyph(face, key.glyph_id)?;
+            self.insert_glyph(key, glyph_data);
+        }
+        let glyph = self.glyphs.get_mut(&key).unwrap();
+        glyph.last_used = self.layout_count;
+        Ok(glyph)
+    }
+
+    pub fn get_glyph(&self, key: GlyphKey) -> Option<&CachedGlyph> {
+        self.glyphs.get(&key)
+    }
+
+    pub fn pages(&self) -> &[AtlasPage] {
+        &self.pages
+    }
+
+    pub fn glyphs_on_page(&self, page: usize) -> impl Iterator<Item = &CachedGlyph> {
+        self.glyphs.values().filter(move |glyph| glyph.page == page)
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    fn insert_glyph(&mut self, key: GlyphKey, data: GlyphData) {
+        let (width, height) = (data.width as u32, data.height as u32);
+        let (page, allocation) = self.allocate(width, height);
+        self.pages[page].generation += 1;
+        self.glyphs.insert(
+            key,
+            CachedGlyph {
+                data,
+                page,
+                allocation,
+                last_used: self.layout_count,
+            },
+        );
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> (usize, Allocation) {
+        loop {
+            for (index, page) in self.pages.iter_mut().enumerate() {
+                if let Some(allocation) = page.packer.allocate(width, height) {
+                    return (index, allocation);
+                }
+            }
+            if self.pages.len() >= MAX_PAGES && self.evict_lru() {
                 continue;
             }
-            texture.replace_region(
-                region,
-                0,
-                all_char_bitmaps[i].as_ptr() as *const _,
-                all_char_widths[i] * 1,
-            );
+            //either under the page limit or everything is pinned by the current layout
+            self.pages.push(AtlasPage {
+                packer: ShelfPacker::new(PAGE_SIZE.max(width + 1), PAGE_SIZE.max(height + 1), 1),
+                generation: 0,
+            });
         }
+    }
+
+    //returns false if every glyph is in use by the current layout
+    fn evict_lru(&mut self) -> bool {
+        let lru = self
+            .glyphs
+            .iter()
+            .filter(|(_, glyph)| glyph.last_used < self.layout_count)
+            .min_by_key(|(_, glyph)| glyph.last_used)
+            .map(|(&key, _)| key);
+        match lru.and_then(|key| self.glyphs.remove(&key)) {
+            Some(glyph) => {
+                let page = &mut self.pages[glyph.page];
+                page.packer.deallocate(glyph.allocation);
+                page.generation += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freetype::{
+        Library,
+        ffi::{FT_BBox, FT_Vector},
+    };
+
+    use super::*;
+    use crate::text::{
+        Font,
+        loader::{FontLoader, FontSource},
+    };
+
+    //a quarter of a page, padding included
+    const QUARTER: u32 = PAGE_SIZE / 2 - 1;
+
+    fn font() -> Font {
+        let lib = Library::init().unwrap();
+        FontLoader::new(&lib)
+            .load(&FontSource::Embedded, 20)
+            .unwrap()
+    }
 
-        // let atlas = Atlas{ texture, max_width, max_height, bitmaps: all_char_bitmaps, advances: all_char_advances };
-        let atlas = Atlas {
-            texture,
-            max_width,
-            max_height: padded_height, //max height is a bad label, should rename to slot_height maybe
-            advances: all_char_advances,
-            cboxes: all_char_cboxes,
+    fn key(glyph_id: u32) -> GlyphKey {
+        GlyphKey { font: 1, glyph_id }
+    }
+
+    //made up glyphs so the pages fill up quickly
+    fn insert(atlas: &mut Atlas, glyph_id: u32, size: u32) {
+        let data = GlyphData {
+            bitmap: vec![0; (size * size) as usize],
+            width: size as u64,
+            height: size as u64,
+            advance: FT_Vector { x: 0, y: 0 },
+            cbox: FT_BBox {
+                xMin: 0,
+                yMin: 0,
+                xMax: 0,
+                yMax: 0,
+            },
         };
-        Ok(atlas)
+        atlas.insert_glyph(key(glyph_id), data);
+    }
+
+    #[test]
+    fn ascii_is_packed_up_front() {
+        let atlas = Atlas::new(&font().face).unwrap();
+        assert_eq!(atlas.pages().len(), 1);
+        assert!(atlas.len() > 1);
+        assert!(atlas.line_height > 0.0);
+    }
+
+    #[test]
+    fn full_pages_grow_into_new_ones() {
+        let mut atlas = Atlas::new(&font().face).unwrap();
+        for glyph_id in 0..6 {
+            insert(&mut atlas, glyph_id, QUARTER);
+        }
+        assert!(atlas.pages().len() >= 2);
+        let first = atlas.get_glyph(key(0)).unwrap();
+        let last = atlas.get_glyph(key(5)).unwrap();
+        assert_ne!(first.page, last.page);
+        assert!(atlas.pages()[last.page].generation() > 0);
     }
 
-    pub fn get_advance(&self, index: usize) -> f32 {
-        self.advances[index].x as f32 / 64.0
+    #[test]
+    fn oversized_glyphs_get_a_page_of_their_own() {
+        let mut atlas = Atlas::new(&font().face).unwrap();
+        insert(&mut atlas, 0, PAGE_SIZE + 10);
+        let glyph = atlas.get_glyph(key(0)).unwrap();
+        assert_eq!(atlas.pages()[glyph.page].packer.size().0, PAGE_SIZE + 11);
+    }
+
+    #[test]
+    fn glyphs_in_the_current_layout_are_never_evicted() {
+        let mut atlas = Atlas::new(&font().face).unwrap();
+        atlas.begin_layout();
+        let mut glyph_id = 0;
+        while atlas.pages().len() <= MAX_PAGES {
+            insert(&mut atlas, glyph_id, QUARTER);
+            glyph_id += 1;
+        }
+        //the ascii from before begin_layout can go, nothing since then
+        assert!((0..glyph_id).all(|glyph_id| atlas.get_glyph(key(glyph_id)).is_some()));
+    }
+
+    #[test]
+    fn least_recently_used_glyphs_make_room_at_the_page_limit() {
+        let font = font();
+        let mut atlas = Atlas::new(&font.face).unwrap();
+        atlas.begin_layout();
+        let mut glyph_id = 0;
+        while atlas.pages().len() < MAX_PAGES {
+            insert(&mut atlas, glyph_id, QUARTER);
+            glyph_id += 1;
+        }
+        let old = glyph_id;
+
+        //only glyph 0 is used again, so everything else from the last layout can go
+        atlas.begin_layout();
+        atlas.glyph(&font.face, key(0)).unwrap();
+        for glyph_id in old..old + 8 {
+            insert(&mut atlas, glyph_id, QUARTER);
+        }
+        assert_eq!(atlas.pages().len(), MAX_PAGES);
+        assert!(atlas.get_glyph(key(0)).is_some());
+        assert!((old..old + 8).all(|glyph_id| atlas.get_glyph(key(glyph_id)).is_some()));
+        assert!((1..old).any(|glyph_id| atlas.get_glyph(key(glyph_id)).is_none()));
     }
 }
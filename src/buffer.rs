@@ -0,0 +1,363 @@
+use std::{
+    io::{self, Read, Write},
+    ops::{Range, RangeBounds},
+};
+
+use ropey::{Rope, RopeBuilder, RopeSlice, iter::Chars};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
+
+//rope backed so inserts/deletes are O(log n) instead of shifting the whole string
+//all indices are char indices, not byte offsets, byte offsets never leave this file
+//the caret and deletions move between extended grapheme clusters, see next_grapheme_boundary
+pub struct TextBuffer {
+    rope: Rope,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self { rope: Rope::new() }
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        Ok(Self {
+            rope: Rope::from_reader(reader)?,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.rope.write_to(writer)
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_chars() == 0
+    }
+
+    pub fn char(&self, char_idx: usize) -> char {
+        self.rope.char(char_idx)
+    }
+
+    pub fn get_char(&self, char_idx: usize) -> Option<char> {
+        self.rope.get_char(char_idx)
+    }
+
+    pub fn chars(&self) -> Chars<'_> {
+        self.rope.chars()
+    }
+
+    pub fn chars_at(&self, char_idx: usize) -> Chars<'_> {
+        self.rope.chars_at(char_idx)
+    }
+
+    pub fn insert_char(&mut self, char_idx: usize, char: char) {
+        self.rope.insert_char(char_idx, char);
+    }
+
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        self.rope.insert(char_idx, text);
+    }
+
+    pub fn push(&mut self, char: char) {
+        self.rope.insert_char(self.rope.len_chars(), char);
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let last = self.rope.len_chars().checked_sub(1)?;
+        let char = self.rope.char(last);
+        self.rope.remove(last..);
+        Some(char)
+    }
+
+    pub fn remove<R: RangeBounds<usize>>(&mut self, char_range: R) {
+        self.rope.remove(char_range);
+    }
+
+    pub fn slice<R: RangeBounds<usize>>(&self, char_range: R) -> RopeSlice<'_> {
+        self.rope.slice(char_range)
+    }
+
+    //line includes its trailing line break, if any
+    pub fn line(&self, line_idx: usize) -> RopeSlice<'_> {
+        self.rope.line(line_idx)
+    }
+
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.rope.char_to_line(char_idx)
+    }
+
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        self.rope.line_to_char(line_idx)
+    }
+
+    //index of the last char in range matching pred, searching backwards from its end
+    pub fn rfind_in<P: Fn(char) -> bool>(&self, range: Range<usize>, pred: P) -> Option<usize> {
+        let mut chars = self.rope.chars_at(range.end);
+        let mut index = range.end;
+        while index > range.start
+            && let Some(char) = chars.prev()
+        {
+            index -= 1;
+            if pred(char) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    //start of the grapheme cluster after the one at char_idx, or len_chars at the end
+    pub fn next_grapheme_boundary(&self, char_idx: usize) -> usize {
+        self.grapheme_boundary(char_idx, true)
+    }
+
+    //start of the grapheme cluster before char_idx, or 0
+    pub fn prev_grapheme_boundary(&self, char_idx: usize) -> usize {
+        self.grapheme_boundary(char_idx, false)
+    }
+
+    pub fn is_grapheme_boundary(&self, char_idx: usize) -> bool {
+        char_idx >= self.len_chars() || self.prev_grapheme_boundary(char_idx + 1) == char_idx
+    }
+
+    //extended grapheme clusters with the char index each one starts at, range should start
+    //on a cluster boundary
+    pub fn graphemes(&self, range: Range<usize>) -> impl Iterator<Item = (usize, RopeSlice<'_>)> {
+        let mut start = range.start;
+        let end = range.end.min(self.len_chars());
+        std::iter::from_fn(move || {
+            if start >= end {
+                return None;
+            }
+            let end = self.next_grapheme_boundary(start);
+            let grapheme = (start, self.rope.slice(start..end));
+            start = end;
+            Some(grapheme)
+        })
+    }
+
+    //walks the rope's chunks with a grapheme cursor instead of copying the text out
+    fn grapheme_boundary(&self, char_idx: usize, forward: bool) -> usize {
+        let byte_idx = self.rope.char_to_byte(char_idx);
+        let mut cursor = GraphemeCursor::new(byte_idx, self.rope.len_bytes(), true);
+        let (mut chunk, mut chunk_start, _, _) = self.rope.chunk_at_byte(byte_idx);
+        loop {
+            let boundary = match forward {
+                true => cursor.next_boundary(chunk, chunk_start),
+                false => cursor.prev_boundary(chunk, chunk_start),
+            };
+            match boundary {
+                Ok(Some(boundary)) => return self.rope.byte_to_char(boundary),
+                Ok(None) if forward => return self.len_chars(),
+                Ok(None) => return 0,
+                Err(GraphemeIncomplete::NextChunk) => {
+                    (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(chunk_start + chunk.len());
+                }
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(chunk_start - 1);
+                }
+                Err(GraphemeIncomplete::PreContext(context_end)) => {
+                    let (context, context_start, _, _) = self.rope.chunk_at_byte(context_end - 1);
+                    cursor.provide_context(context, context_start);
+                }
+                //only returned for a cursor that was given the wrong chunk
+                Err(GraphemeIncomplete::InvalidOffset) => unreachable!(),
+            }
+        }
+    }
+
+    //uax #29 word segment containing char_idx, which may be whitespace or punctuation
+    pub fn word_at(&self, char_idx: usize) -> Range<usize> {
+        if char_idx >= self.len_chars() {
+            return char_idx..char_idx;
+        }
+        self.line_segments(self.char_to_line(char_idx))
+            .into_iter()
+            .map(|(range, _)| range)
+            .find(|range| range.contains(&char_idx))
+            .unwrap_or(char_idx..char_idx + 1)
+    }
+
+    //end of the word char_idx is in, or of the next one, crossing lines if it has to
+    pub fn next_word_end(&self, char_idx: usize) -> usize {
+        for line_idx in self.char_to_line(char_idx)..self.len_lines() {
+            let word_end = self
+                .line_segments(line_idx)
+                .into_iter()
+                .find(|(range, is_word)| *is_word && range.end > char_idx)
+                .map(|(range, _)| range.end);
+            if let Some(word_end) = word_end {
+                return word_end;
+            }
+        }
+        self.len_chars()
+    }
+
+    //start of the next word or run of punctuation after char_idx, like vim's w
+    pub fn next_word_start(&self, char_idx: usize) -> usize {
+        for line_idx in self.char_to_line(char_idx)..self.len_lines() {
+            let word_start = self
+                .line_segments(line_idx)
+                .into_iter()
+                .find(|(range, _)| {
+                    range.start > char_idx
+                        && self
+                            .rope
+                            .slice(range.clone())
+                            .chars()
+                            .any(|char| !char.is_whitespace())
+                })
+                .map(|(range, _)| range.start);
+            if let Some(word_start) = word_start {
+                return word_start;
+            }
+        }
+        self.len_chars()
+    }
+
+    //start of the word char_idx is in, or of the previous one
+    pub fn prev_word_start(&self, char_idx: usize) -> usize {
+        for line_idx in (0..=self.char_to_line(char_idx)).rev() {
+            let word_start = self
+                .line_segments(line_idx)
+                .into_iter()
+                .rev()
+                .find(|(range, is_word)| *is_word && range.start < char_idx)
+                .map(|(range, _)| range.start);
+            if let Some(word_start) = word_start {
+                return word_start;
+            }
+        }
+        0
+    }
+
+    //word boundaries never cross a line break, so segmenting a line at a time is enough
+    //ranges are char indices into the whole text, the bool is whether it's a word
+    fn line_segments(&self, line_idx: usize) -> Vec<(Range<usize>, bool)> {
+        let text = self.rope.line(line_idx).to_string();
+        let mut start = self.rope.line_to_char(line_idx);
+        text.split_word_bounds()
+            .map(|segment| {
+                let end = start + segment.chars().count();
+                let range = start..end;
+                start = end;
+                (range, segment.chars().any(char::is_alphanumeric))
+            })
+            .collect()
+    }
+}
+
+//builds a buffer a piece at a time, so a big file never has to be in one string
+pub struct TextBufferBuilder {
+    builder: RopeBuilder,
+}
+
+impl TextBufferBuilder {
+    pub fn new() -> Self {
+        Self {
+            builder: RopeBuilder::new(),
+        }
+    }
+
+    pub fn append(&mut self, text: &str) {
+        self.builder.append(text);
+    }
+
+    pub fn finish(self) -> TextBuffer {
+        TextBuffer {
+            rope: self.builder.finish(),
+        }
+    }
+}
+
+impl Default for TextBufferBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for TextBuffer {
+    fn from(value: &str) -> Self {
+        Self {
+            rope: Rope::from_str(value),
+        }
+    }
+}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.rope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grapheme_boundaries_skip_combining_marks() {
+        let text_buffer = TextBuffer::from("ae\u{301}\r\n");
+        assert_eq!(text_buffer.next_grapheme_boundary(1), 3);
+        assert_eq!(text_buffer.prev_grapheme_boundary(3), 1);
+        assert!(!text_buffer.is_grapheme_boundary(2));
+        //crlf is one cluster
+        assert_eq!(text_buffer.next_grapheme_boundary(3), 5);
+        let graphemes: Vec<_> = text_buffer
+            .graphemes(0..5)
+            .map(|(index, grapheme)| (index, grapheme.to_string()))
+            .collect();
+        assert_eq!(
+            graphemes,
+            [
+                (0, "a".to_string()),
+                (1, "e\u{301}".to_string()),
+                (3, "\r\n".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn word_motions_cross_lines() {
+        let text_buffer = TextBuffer::from("foo, bar baz\rqux");
+        assert_eq!(text_buffer.word_at(6), 5..8);
+        //punctuation counts as a word start, whitespace doesn't
+        assert_eq!(text_buffer.next_word_start(0), 3);
+        assert_eq!(text_buffer.next_word_start(3), 5);
+        assert_eq!(text_buffer.next_word_start(9), 13);
+        assert_eq!(text_buffer.next_word_end(0), 3);
+        assert_eq!(text_buffer.next_word_end(12), 16);
+        assert_eq!(text_buffer.prev_word_start(13), 9);
+        assert_eq!(text_buffer.prev_word_start(9), 5);
+        assert_eq!(text_buffer.prev_word_start(0), 0);
+    }
+
+    #[test]
+    fn rfind_in_stays_in_its_range() {
+        let text_buffer = TextBuffer::from("a b c");
+        assert_eq!(text_buffer.rfind_in(0..4, char::is_whitespace), Some(3));
+        assert_eq!(text_buffer.rfind_in(0..1, char::is_whitespace), None);
+        assert_eq!(text_buffer.rfind_in(2..3, char::is_whitespace), None);
+    }
+
+    #[test]
+    fn builder_joins_pieces() {
+        let mut builder = TextBufferBuilder::new();
+        builder.append("one\r");
+        builder.append("two");
+        let text_buffer = builder.finish();
+        assert_eq!(text_buffer.to_string(), "one\rtwo");
+        assert_eq!(text_buffer.len_lines(), 2);
+        assert_eq!(text_buffer.line_to_char(1), 4);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fmt, path::PathBuf};
+
+use crate::file_format::{Encoding, LineEnding};
+
+pub const USAGE: &str = "usage: txt-rs [--readonly] [--line N] [--column N] [+N]
+              [--line-ending STYLE] [--encoding ENCODING] [--[no-]bom] [FILE]...
+
+Opens each FILE in its own buffer, files that don't exist are created on save.
+With no FILE, text.txt in the working directory is used. Files are saved with
+the line endings and encoding they were opened with unless converted.
+
+  -r, --readonly        never modify or save the files
+  -l, --line N          put the cursor on line N of the first file
+  -c, --column N        and on column N of that line
+  +N                    same as --line N, as passed by programs using $EDITOR
+  --line-ending STYLE   convert the files to lf, crlf or cr line endings on save
+  --encoding ENCODING   convert the files to utf-8, utf-16le or utf-16be on save
+  --bom, --no-bom       add or drop the byte order mark on save, utf-16 gets one
+                        by default when converting to it
+  -h, --help            print this";
+
+//parsed command line, lines and columns are 1 based like in other editors
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    pub paths: Vec<PathBuf>,
+    pub readonly: bool,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    //formats to convert the files to
+    pub line_ending: Option<LineEnding>,
+    pub encoding: Option<Encoding>,
+    pub bom: Option<bool>,
+    pub help: bool,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    MissingValue(String),
+    InvalidNumber(String, String),
+    InvalidValue(String, String),
+    UnknownFlag(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingValue(flag) => write!(f, "{flag} needs a value"),
+            CliError::InvalidNumber(flag, value) => {
+                write!(f, "{flag} needs a number from 1 up, got `{value}`")
+            }
+            CliError::InvalidValue(flag, value) => write!(f, "{flag} doesn't take `{value}`"),
+            CliError::UnknownFlag(flag) => write!(f, "unknown flag {flag}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Args {
+    //without the program name, i.e. env::args().skip(1)
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, CliError> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        let mut only_paths = false;
+        while let Some(arg) = args.next() {
+            if only_paths {
+                parsed.paths.push(arg.into());
+                continue;
+            }
+            //--line=3 and --line 3 both work
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError::MissingValue(flag.to_string()))
+            };
+            match flag {
+                "--" => only_paths = true,
+                "-h" | "--help" => parsed.help = true,
+                "-r" | "--readonly" => parsed.readonly = true,
+                "-l" | "--line" => parsed.line = Some(parse_position(flag, &value()?)?),
+                "-c" | "--column" => parsed.column = Some(parse_position(flag, &value()?)?),
+                "--line-ending" => parsed.line_ending = Some(parse_value(flag, &value()?)?),
+                "--encoding" => parsed.encoding = Some(parse_value(flag, &value()?)?),
+                "--bom" => parsed.bom = Some(true),
+                "--no-bom" => parsed.bom = Some(false),
+                _ if flag.starts_with('+') && flag.len() > 1 => {
+                    parsed.line = Some(parse_position("+N", &flag[1..])?);
+                }
+                _ if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(CliError::UnknownFlag(flag.to_string()));
+                }
+                _ => parsed.paths.push(PathBuf::from(&arg)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_position(flag: &str, value: &str) -> Result<usize, CliError> {
+    match value.parse::<usize>() {
+        Ok(position) if position > 0 => Ok(position),
+        _ => Err(CliError::InvalidNumber(flag.to_string(), value.to_string())),
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::InvalidValue(flag.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, CliError> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn lines_from_plus_n_and_the_flag() {
+        assert_eq!(parse(&["+12", "a.txt"]).unwrap().line, Some(12));
+        let args = parse(&["--line", "3", "-c", "4"]).unwrap();
+        assert_eq!((args.line, args.column), (Some(3), Some(4)));
+        assert_eq!(parse(&["--line=7"]).unwrap().line, Some(7));
+    }
+
+    #[test]
+    fn positions_start_at_one() {
+        assert!(matches!(
+            parse(&["--line", "0"]),
+            Err(CliError::InvalidNumber(..))
+        ));
+        assert!(matches!(parse(&["+x"]), Err(CliError::InvalidNumber(..))));
+        assert!(matches!(
+            parse(&["--line"]),
+            Err(CliError::MissingValue(..))
+        ));
+    }
+
+    #[test]
+    fn everything_after_a_double_dash_is_a_file() {
+        let args = parse(&["a.txt", "--", "-b.txt", "+3"]).unwrap();
+        let paths: Vec<PathBuf> = ["a.txt", "-b.txt", "+3"].map(PathBuf::from).into();
+        assert_eq!(args.paths, paths);
+        assert_eq!(args.line, None);
+    }
+
+    #[test]
+    fn formats_and_flags() {
+        let args = parse(&[
+            "-r",
+            "--line-ending",
+            "crlf",
+            "--encoding=utf-16le",
+            "--no-bom",
+        ]);
+        let args = args.unwrap();
+        assert!(args.readonly);
+        assert_eq!(args.line_ending, Some(LineEnding::CrLf));
+        assert_eq!(args.encoding, Some(Encoding::Utf16Le));
+        assert_eq!(args.bom, Some(false));
+        assert!(matches!(
+            parse(&["--line-ending", "dos"]),
+            Err(CliError::InvalidValue(..))
+        ));
+        assert!(
+            matches!(parse(&["--frobnicate"]), Err(CliError::UnknownFlag(flag)) if flag == "--frobnicate")
+        );
+        assert!(matches!(
+            parse(&["-x", "a.txt"]),
+            Err(CliError::UnknownFlag(..))
+        ));
+    }
+}
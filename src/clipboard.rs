@@ -0,0 +1,37 @@
+//where copy/cut/paste go, the frontend hands the editor the system one
+pub trait Clipboard {
+    fn read_text(&mut self) -> Option<String>;
+    fn write_text(&mut self, text: &str);
+}
+
+//default until a frontend sets one, also what tests use
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    text: Option<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn read_text(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn write_text(&mut self, text: &str) {
+        self.text = Some(text.to_string());
+    }
+}
+
+//the buffer only uses \r internally, pasted \r\n and \n get turned into that
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\r").replace('\n', "\r")
+}
+
+//other apps expect \n on the clipboard, a bare \r shows up as one long line in most of them
+pub fn clipboard_line_endings(text: &str) -> String {
+    text.replace('\r', "\n")
+}
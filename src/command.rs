@@ -0,0 +1,100 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextCommand {
+    Char(char),
+    Backspace,
+    Newline,
+    Unknown,
+}
+impl From<char> for TextCommand {
+    fn from(value: char) -> Self {
+        match value {
+            '\u{7f}' => TextCommand::Backspace,
+            '\r' => TextCommand::Newline,
+            _ if value.is_control() => TextCommand::Unknown,
+            _ => TextCommand::Char(value),
+        }
+    }
+}
+
+//where a caret goes, vertical ones keep the caret's goal x
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    DocStart,
+    DocEnd,
+    WordLeft,
+    WordRight,
+    //a paragraph is a logical line, like in other mac text views
+    ParagraphUp,
+    ParagraphDown,
+    //ends of the logical line, unlike LineStart/LineEnd which stop at wraps
+    ParagraphStart,
+    ParagraphEnd,
+    //left/right that stop at the ends of the logical line, vim's h and l
+    LeftInLine,
+    RightInLine,
+    //vim's w, and e which lands on the word's last char rather than after it
+    NextWordStart,
+    WordEnd,
+    //w under an operator, stops at the end of the line instead of going on to the next one
+    NextWordStartInLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    Move(Motion),
+    Select(Motion), //moves the head and keeps the anchor
+    SelectWord,
+    SelectLine,
+    SelectAll,
+    DeleteForward,
+    DeleteWordBackward,
+    DeleteWordForward,
+    AddCursor(Motion), //a new caret a line above/below the primary
+    CollapseCursors,
+    Copy,
+    Cut,
+    Paste,
+    //vim's linewise registers, whole lines that always end in a newline, see vim
+    CopyLines,
+    CutLines,
+    PasteLines { above: bool, count: usize },
+    GoToLine(usize), //0 based, see Editor::go_to
+    //emacs style region and kill ring, see kill_ring
+    SetMark, //movement extends the selection until the next edit or Cancel
+    Cancel,
+    Kill(Motion), //from each caret to where the motion would take it
+    KillLine,     //to the end of the paragraph, or its newline if already there
+    KillRegion,
+    CopyRegion,
+    Yank,
+    YankPop,
+    Undo,
+    Redo,
+    Save, //the editor only flags it, whoever owns the file does the saving, see document
+    Unknown,
+}
+
+//held modifier keys, named after the mac ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub option: bool,
+    pub command: bool,
+}
+
+//what a key chord is bound to, see keymap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCommand {
+    TC(TextCommand),
+    CC(ControlCommand),
+    Unknown,
+}
@@ -0,0 +1,172 @@
+use std::{collections::BTreeMap, env, fmt, fs, io, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    keymap::{Binding, KeyChord, Preset},
+    text::loader::FontSource,
+};
+
+//read from $TXT_RS_CONFIG, or ~/.config/txt-rs/config.toml, anything left out keeps its default
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub font: FontConfig,
+    pub keymap: KeymapConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FontConfig {
+    pub primary: FontSource,
+    pub size: u32,
+    //consulted in order for chars the primary doesn't have, ones that fail to load are skipped
+    pub fallbacks: Vec<FontSource>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            primary: FontSource::Embedded,
+            size: 100,
+            fallbacks: default_fallbacks(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "RawKeymapConfig")]
+pub struct KeymapConfig {
+    pub preset: Preset,
+    //modal editing in front of the bindings, which still apply in insert mode
+    pub vim: bool,
+    //applied over the preset's bindings, e.g. "cmd+shift+k" = "select-line"
+    pub bindings: BTreeMap<KeyChord, Binding>,
+    //entries of [keymap.bindings] that didn't parse, one typo shouldn't throw away the whole config
+    pub invalid_bindings: Vec<String>,
+}
+
+//bindings are kept as plain toml until each one is parsed on its own
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RawKeymapConfig {
+    preset: Preset,
+    vim: bool,
+    bindings: BTreeMap<String, toml::Value>,
+}
+
+impl From<RawKeymapConfig> for KeymapConfig {
+    fn from(raw: RawKeymapConfig) -> Self {
+        let mut bindings = BTreeMap::new();
+        let mut invalid_bindings = Vec::new();
+        for (chord, value) in raw.bindings {
+            let binding = match value.as_str() {
+                Some(command) => chord
+                    .parse::<KeyChord>()
+                    .and_then(|key_chord| Ok((key_chord, command.parse::<Binding>()?)))
+                    .map_err(|err| err.to_string()),
+                None => Err(format!("expected a command name, got {value}")),
+            };
+            match binding {
+                Ok((key_chord, binding)) => {
+                    bindings.insert(key_chord, binding);
+                }
+                Err(err) => invalid_bindings.push(format!("\"{chord}\": {err}")),
+            }
+        }
+        Self {
+            preset: raw.preset,
+            vim: raw.vim,
+            bindings,
+            invalid_bindings,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn default_fallbacks() -> Vec<FontSource> {
+    [
+        "Apple Symbols",
+        "Hiragino Sans GB",
+        "Apple SD Gothic Neo",
+        "Apple Color Emoji",
+    ]
+    .map(|family| FontSource::System(family.to_string()))
+    .to_vec()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn default_fallbacks() -> Vec<FontSource> {
+    ["DejaVu Sans", "Noto Sans CJK SC", "Noto Color Emoji"]
+        .map(|family| FontSource::System(family.to_string()))
+        .to_vec()
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read {}: {err}", path.display()),
+            ConfigError::Parse(path, err) => write!(f, "invalid config {}: {err}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("TXT_RS_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/txt-rs/config.toml"))
+    }
+
+    //no config file isn't an error, it just means defaults
+    pub fn load() -> Result<Config, ConfigError> {
+        match Config::path() {
+            Some(path) => Config::load_from(path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<Config, ConfigError> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(ConfigError::Io(path, err)),
+        };
+        toml::from_str(&text).map_err(|err| ConfigError::Parse(path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_bindings_are_skipped_not_fatal() {
+        let config: Config = toml::from_str(
+            r#"
+            [keymap]
+            vim = true
+
+            [keymap.bindings]
+            "cmd+k" = "select-line"
+            "cmd+j" = "no-such-command"
+            "hyper+x" = "copy"
+            "cmd+u" = 3
+            "#,
+        )
+        .unwrap();
+        assert!(config.keymap.vim);
+        assert_eq!(config.keymap.bindings.len(), 1);
+        assert_eq!(config.keymap.invalid_bindings.len(), 3);
+        assert!(config.keymap.invalid_bindings[0].contains("no-such-command"));
+    }
+}
@@ -0,0 +1,742 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Read},
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+use memmap2::Mmap;
+use ropey::RopeSlice;
+
+use crate::{
+    buffer::TextBuffer,
+    editor::{Editor, InputEvent},
+    file_format::FileFormat,
+    merge::merge3,
+    recovery::Recovery,
+};
+
+//files at least this big, in bytes on disk or chars in the editor, are decoded from a map of
+//the file rather than read into a buffer first, and the rope is the only copy of their text,
+//so there's no base to merge against, no recovery snapshot and no undo for reloading them
+#[cfg(not(test))]
+const LARGE_FILE_SIZE: usize = 16 << 20;
+//small enough for tests to write large files
+#[cfg(test)]
+const LARGE_FILE_SIZE: usize = 4 << 10;
+
+//an editor and the file it came from
+pub struct Document {
+    path: PathBuf,
+    editor: Editor,
+    //line endings and encoding the file gets saved with, and the ones it has on disk
+    format: FileFormat,
+    saved_format: FileFormat,
+    //keeps unsaved changes somewhere a crash won't lose them
+    recovery: Option<Recovery>,
+    //the last time the journal couldn't be written, editing and saving carry on regardless
+    recovery_error: Option<io::Error>,
+    //the text as last loaded or saved, what a merge compares both sides against, large files
+    //don't keep a copy so can't be merged
+    base: Option<String>,
+    //the file as last seen, None if it didn't exist
+    disk: Option<DiskStamp>,
+    //the file went away while open, saving puts it back
+    deleted: bool,
+    //what changed on disk while there were unsaved changes, until resolve_conflict
+    theirs: Option<(TextBuffer, FileFormat)>,
+}
+
+//enough to notice a file changed without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl DiskStamp {
+    fn of(path: &Path) -> io::Result<Option<DiskStamp>> {
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(Some(DiskStamp {
+                modified: metadata.modified()?,
+                len: metadata.len(),
+            })),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+//what check_disk found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskChange {
+    Unchanged,
+    //there were no unsaved changes, so the editor has the new text
+    Reloaded,
+    Deleted,
+    //changed while there were unsaved changes, the frontend asks what to do and calls
+    //resolve_conflict with the answer
+    Conflict,
+    //a large file changed, it isn't read until the frontend asks for it with reload_from_disk
+    LargeFileChanged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictChoice {
+    //three way merge of the unsaved changes and the ones on disk, see merge, same as
+    //KeepMine when can_merge is false
+    Merge,
+    //saving will overwrite the changes on disk
+    KeepMine,
+    //throws away the unsaved changes, undo still has them
+    Reload,
+}
+
+impl Document {
+    //whatever text the editor starts with counts as saved, in the format from load, unless its
+    //line endings were mixed, saving changes those so it starts out with unsaved changes
+    pub fn new(path: PathBuf, mut editor: Editor, format: FileFormat) -> Document {
+        editor.mark_saved();
+        let disk = DiskStamp::of(&path).ok().flatten();
+        Document {
+            base: (!is_large(editor.text_buffer())).then(|| text_of(&editor)),
+            path,
+            editor,
+            format: format.normalized(),
+            saved_format: format,
+            recovery: None,
+            recovery_error: None,
+            disk,
+            deleted: false,
+            theirs: None,
+        }
+    }
+
+    //unsaved changes from now on get journaled in dir, see recovered_text for ones left by
+    //a session that crashed
+    pub fn set_recovery_dir(&mut self, dir: &Path) {
+        //a snapshot of a large file would take as long to write as saving it
+        if is_large(self.editor.text_buffer()) {
+            return;
+        }
+        self.editor.record_edits();
+        self.recovery = Some(Recovery::new(dir, &self.path));
+    }
+
+    //the text as the editor wants it and the format to write it back in, files that don't
+    //exist yet start out empty and get created on save
+    pub fn load(path: &Path) -> io::Result<(TextBuffer, FileFormat)> {
+        match File::open(path) {
+            Ok(file) => decode_file(file),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                Ok((TextBuffer::new(), FileFormat::default()))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    //same as the editor's, and saves if the key was the save command
+    pub fn handle_input(&mut self, event: InputEvent) -> io::Result<bool> {
+        let changed = self.editor.handle_input(event);
+        if self.editor.take_save_request() {
+            self.save()
+                .map_err(|err| io::Error::new(err.kind(), format!("couldn't save: {err}")))?;
+        }
+        self.journal_edits();
+        Ok(changed)
+    }
+
+    //why unsaved changes stopped being kept for recovery, if they did since the last call
+    pub fn take_recovery_error(&mut self) -> Option<io::Error> {
+        self.recovery_error.take()
+    }
+
+    fn journal_edits(&mut self) {
+        let Some(recovery) = &mut self.recovery else {
+            return;
+        };
+        let edits = self.editor.take_edits();
+        let journaled = match self.editor.is_dirty() {
+            true => recovery.record(&edits, &self.editor),
+            //e.g. undone back to what's on disk
+            false if recovery.is_active() => recovery.clear(),
+            false => Ok(()),
+        };
+        if let Err(err) = journaled {
+            self.recovery_error = Some(err);
+        }
+    }
+
+    //call every so often, rewrites the recovery snapshot if the journal has grown since
+    pub fn autosave(&mut self) -> io::Result<()> {
+        match &mut self.recovery {
+            Some(recovery) => recovery.autosave(&self.editor),
+            None => Ok(()),
+        }
+    }
+
+    //unsaved changes a session that didn't exit cleanly left for this file, if they differ
+    //from what's in the editor
+    pub fn recovered_text(&self) -> io::Result<Option<String>> {
+        let Some(recovery) = &self.recovery else {
+            return Ok(None);
+        };
+        let Some(text) = recovery.load()? else {
+            return Ok(None);
+        };
+        let mut current = Vec::new();
+        self.editor.write_to(&mut current)?;
+        Ok((text.as_bytes() != current).then_some(text))
+    }
+
+    //puts the recovered text in the editor as an undoable, unsaved change
+    pub fn restore(&mut self, text: &str) -> io::Result<()> {
+        self.editor.replace_text(text);
+        self.editor.take_edits();
+        match &mut self.recovery {
+            Some(recovery) => recovery.snapshot(&self.editor),
+            None => Ok(()),
+        }
+    }
+
+    //throws away what recovered_text found, or this session's unsaved changes
+    pub fn discard_recovery(&mut self) -> io::Result<()> {
+        match &mut self.recovery {
+            Some(recovery) => recovery.clear(),
+            None => Ok(()),
+        }
+    }
+
+    //see write_atomic, a crash or full disk mid-save leaves the old file as it was
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.editor.is_readonly() {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "opened read only",
+            ));
+        }
+        //saving through a symlink replaces what it points to, not the link
+        let target = match fs::canonicalize(&self.path) {
+            Ok(target) => target,
+            Err(err) if err.kind() == ErrorKind::NotFound => self.path.clone(),
+            Err(err) => return Err(err),
+        };
+        let text = content(self.editor.text_buffer());
+        write_atomic(&target, |writer| self.format.write(text.chunks(), writer))?;
+        self.editor.mark_saved();
+        self.saved_format = self.format;
+        self.base = (!is_large(self.editor.text_buffer())).then(|| text_of(&self.editor));
+        self.disk = DiskStamp::of(&self.path)?;
+        self.deleted = false;
+        //the file is saved either way, the recovery files only matter until it is
+        if let Err(err) = self.discard_recovery() {
+            self.recovery_error = Some(err);
+        }
+        Ok(())
+    }
+
+    //converting to another format, or the file going away, is an unsaved change too
+    pub fn is_dirty(&self) -> bool {
+        self.editor.is_dirty() || self.format != self.saved_format || self.deleted
+    }
+
+    //call every so often, notices the file being changed by something else, e.g. git, only
+    //reads it if its size or modification time changed
+    pub fn check_disk(&mut self) -> io::Result<DiskChange> {
+        let stamp = DiskStamp::of(&self.path)?;
+        if stamp == self.disk || self.theirs.is_some() {
+            return Ok(DiskChange::Unchanged);
+        }
+        //a file that can't be read or decoded isn't tried again until it changes again
+        self.disk = stamp;
+        let Some(DiskStamp { len, .. }) = stamp else {
+            self.deleted = true;
+            return Ok(DiskChange::Deleted);
+        };
+        self.deleted = false;
+        //reading and decoding one would stall the frontend every time it changes, e.g. a log
+        //being written to
+        if len >= LARGE_FILE_SIZE as u64 || is_large(self.editor.text_buffer()) {
+            return Ok(DiskChange::LargeFileChanged);
+        }
+        let (text_buffer, format) = decode_file(File::open(&self.path)?)?;
+        //e.g. only touched, or saved by us, without a base the editor's text is what was
+        //last loaded or saved as long as it has no unsaved changes
+        let text = text_buffer.slice(..);
+        let unchanged = match &self.base {
+            Some(base) => text == base.as_str(),
+            None => !self.is_dirty() && text == content(self.editor.text_buffer()),
+        };
+        if unchanged && format == self.saved_format {
+            return Ok(DiskChange::Unchanged);
+        }
+        if !self.is_dirty() {
+            self.reload(text_buffer, format)?;
+            return Ok(DiskChange::Reloaded);
+        }
+        self.theirs = Some((text_buffer, format));
+        Ok(DiskChange::Conflict)
+    }
+
+    //what to do about a DiskChange::Conflict, returns how many conflicts a merge marked
+    pub fn resolve_conflict(&mut self, choice: ConflictChoice) -> io::Result<usize> {
+        let Some((theirs, format)) = self.theirs.take() else {
+            return Ok(0);
+        };
+        let conflicts = match (choice, &self.base) {
+            (ConflictChoice::Reload, _) => return self.reload(theirs, format).map(|_| 0),
+            (ConflictChoice::Merge, Some(base)) => {
+                let (merged, conflicts) = merge3(base, &text_of(&self.editor), &theirs.to_string());
+                self.editor.replace_text(&merged);
+                conflicts
+            }
+            _ => 0,
+        };
+        //a conversion asked for still happens, otherwise the file keeps its new format
+        if self.format == self.saved_format.normalized() {
+            self.format = format.normalized();
+        }
+        self.saved_format = format;
+        if content(self.editor.text_buffer()) == theirs.slice(..) {
+            self.editor.mark_saved();
+        }
+        self.base = (!is_large(self.editor.text_buffer())).then(|| theirs.to_string());
+        self.journal_edits();
+        Ok(conflicts)
+    }
+
+    //reads the file again after DiskChange::LargeFileChanged, throwing away unsaved changes
+    pub fn reload_from_disk(&mut self) -> io::Result<()> {
+        let (text_buffer, format) = decode_file(File::open(&self.path)?)?;
+        self.reload(text_buffer, format)
+    }
+
+    //as an undoable edit, so undoing gets back what was there before, unless either text is
+    //large, then the history goes with the old text
+    fn reload(&mut self, text_buffer: TextBuffer, format: FileFormat) -> io::Result<()> {
+        match is_large(self.editor.text_buffer()) || is_large(&text_buffer) {
+            true => self.editor.set_text_buffer(text_buffer),
+            false => self.editor.replace_text(&text_buffer.to_string()),
+        }
+        self.editor.mark_saved();
+        self.format = format.normalized();
+        self.saved_format = format;
+        self.base = (!is_large(self.editor.text_buffer())).then(|| text_of(&self.editor));
+        self.journal_edits();
+        Ok(())
+    }
+
+    //whether a conflict can be merged, see ConflictChoice
+    pub fn can_merge(&self) -> bool {
+        self.base.is_some()
+    }
+
+    pub fn format(&self) -> FileFormat {
+        self.format
+    }
+
+    //what the next save converts the file to
+    pub fn set_format(&mut self, format: FileFormat) {
+        self.format = format;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn editor(&self) -> &Editor {
+        &self.editor
+    }
+
+    pub fn editor_mut(&mut self) -> &mut Editor {
+        &mut self.editor
+    }
+
+    //file name for the window title, marked if it's read only or has unsaved changes
+    pub fn title(&self) -> String {
+        let name = self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        match (self.editor.is_readonly(), self.is_dirty()) {
+            (true, _) => format!("{name} (read only)"),
+            (false, true) => format!("{name} — Edited"),
+            (false, false) => name,
+        }
+    }
+}
+
+//writes to a temp file next to the target and renames it over the target, so there's
+//either the old file or the new one, never half of each, the target's permissions are kept
+pub(crate) fn write_atomic<F>(target: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let permissions = match fs::metadata(target) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "path doesn't name a file"))?;
+    let temp = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+
+    let written = write_temp(&temp, permissions, write).and_then(|_| fs::rename(&temp, target));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    //makes the rename itself survive a crash, not every platform can open a directory for this
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn text_of(editor: &Editor) -> String {
+    content(editor.text_buffer()).to_string()
+}
+
+//the text without the sentinel
+fn content(text_buffer: &TextBuffer) -> RopeSlice<'_> {
+    text_buffer.slice(..text_buffer.len_chars() - 1)
+}
+
+fn is_large(text_buffer: &TextBuffer) -> bool {
+    text_buffer.len_chars() >= LARGE_FILE_SIZE
+}
+
+//mapping a large file means the only copy of its text is the one being built up in the buffer,
+//it's still all decoded up front as the rope needs its text in memory, only layout is lazy
+fn decode_file(mut file: File) -> io::Result<(TextBuffer, FileFormat)> {
+    if file.metadata()?.len() < LARGE_FILE_SIZE as u64 {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        return FileFormat::decode(&bytes);
+    }
+    //safety: the map only lives while it's decoded, another program truncating the file in
+    //that time is the one way this can go wrong
+    let map = unsafe { Mmap::map(&file)? };
+    FileFormat::decode(&map)
+}
+
+fn write_temp<F>(path: &Path, permissions: Option<fs::Permissions>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let file = File::create(path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()
+}
+
+//how the frontend answers the unsaved changes prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+//what to ask before closing documents with unsaved changes, the frontend shows it with
+//buttons for each CloseChoice and passes the answer to resolve
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosePrompt {
+    pub message: String,
+    pub detail: String,
+    //indices of the unsaved documents
+    pub unsaved: Vec<usize>,
+}
+
+impl ClosePrompt {
+    //None if everything is saved and closing can go ahead
+    pub fn new(documents: &[Document]) -> Option<ClosePrompt> {
+        let unsaved: Vec<usize> = (0..documents.len())
+            .filter(|&index| documents[index].is_dirty())
+            .collect();
+        let message = match unsaved.as_slice() {
+            [] => return None,
+            [index] => format!(
+                "Do you want to save the changes you made to {}?",
+                documents[*index].path.display()
+            ),
+            _ => format!(
+                "You have unsaved changes in {} documents. Do you want to save them?",
+                unsaved.len()
+            ),
+        };
+        Some(ClosePrompt {
+            message,
+            detail: "Your changes will be lost if you don't save them.".to_string(),
+            unsaved,
+        })
+    }
+
+    //true if closing can go ahead, a failed save stops at that document so nothing else is lost
+    pub fn resolve(&self, documents: &mut [Document], choice: CloseChoice) -> io::Result<bool> {
+        match choice {
+            CloseChoice::Save => {
+                for &index in &self.unsaved {
+                    let document = &mut documents[index];
+                    document.save().map_err(|err| {
+                        io::Error::new(
+                            err.kind(),
+                            format!("couldn't save {}: {err}", document.path.display()),
+                        )
+                    })?;
+                }
+                Ok(true)
+            }
+            CloseChoice::Discard => {
+                //anything left behind only gets offered again next time
+                for &index in &self.unsaved {
+                    let _ = documents[index].discard_recovery();
+                }
+                Ok(true)
+            }
+            CloseChoice::Cancel => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{
+        command::Modifiers,
+        editor::tests::{CMD, NONE, editor, press, text},
+        file_format::LineEnding,
+        keymap::Key,
+    };
+
+    //an empty directory of its own for each test, cleared at the start rather than the end
+    //so a failing one can be looked at
+    pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("txt-rs-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn document(path: PathBuf) -> Document {
+        let (text_buffer, format) = Document::load(&path).unwrap();
+        let editor = editor(&text_buffer.to_string());
+        Document::new(path, editor, format)
+    }
+
+    fn key(char: char, modifiers: Modifiers) -> InputEvent {
+        InputEvent::KeyDown(Key::Char(char), modifiers)
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_and_leaves_no_temp() {
+        let dir = scratch_dir("write-atomic");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        write_atomic(&path, |writer| io::Write::write_all(writer, b"new")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(entries(&dir), ["a.txt"]);
+
+        let failed = write_atomic(&path, |writer| {
+            io::Write::write_all(writer, b"half")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(entries(&dir), ["a.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_keeps_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("permissions");
+        let path = dir.join("script.sh");
+        fs::write(&path, "echo hi").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let mut document = document(path.clone());
+        press(document.editor_mut(), "!", NONE);
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo hi!");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn closing_only_asks_about_unsaved_documents() {
+        let dir = scratch_dir("close-prompt");
+        let mut documents = vec![document(dir.join("a.txt")), document(dir.join("b.txt"))];
+        assert_eq!(ClosePrompt::new(&documents), None);
+
+        press(documents[1].editor_mut(), "b", NONE);
+        let prompt = ClosePrompt::new(&documents).unwrap();
+        assert_eq!(prompt.unsaved, [1]);
+        assert!(prompt.message.contains("b.txt"));
+
+        assert!(!prompt.resolve(&mut documents, CloseChoice::Cancel).unwrap());
+        assert!(documents[1].is_dirty());
+        assert!(
+            prompt
+                .resolve(&mut documents, CloseChoice::Discard)
+                .unwrap()
+        );
+        assert!(!dir.join("b.txt").exists());
+        assert!(prompt.resolve(&mut documents, CloseChoice::Save).unwrap());
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "b");
+        assert!(!documents[1].is_dirty());
+        assert_eq!(ClosePrompt::new(&documents), None);
+    }
+
+    #[test]
+    fn a_journal_that_cant_be_written_doesnt_stop_saving() {
+        let dir = scratch_dir("journal-failure");
+        let path = dir.join("a.txt");
+        //a file where the recovery directory should be
+        let recovery_dir = dir.join("recovery");
+        fs::write(&recovery_dir, "").unwrap();
+        let mut document = document(path.clone());
+        document.set_recovery_dir(&recovery_dir);
+        assert!(document.handle_input(key('x', NONE)).unwrap());
+        assert!(document.take_recovery_error().is_some());
+        document.handle_input(key('s', CMD)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x");
+        assert!(!document.is_dirty());
+    }
+
+    #[test]
+    fn mixed_line_endings_open_as_an_unsaved_change() {
+        let dir = scratch_dir("mixed-line-endings");
+        let path = dir.join("a.txt");
+        fs::write(&path, "a\rb\n").unwrap();
+        let mut document = document(path.clone());
+        assert!(document.is_dirty());
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+        assert!(!document.is_dirty());
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Unchanged);
+    }
+
+    fn document_text(document: &Document) -> String {
+        text(document.editor())
+    }
+
+    #[test]
+    fn a_clean_file_changed_on_disk_reloads() {
+        let dir = scratch_dir("reload");
+        let path = dir.join("a.txt");
+        fs::write(&path, "one\n").unwrap();
+        let mut document = document(path.clone());
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Unchanged);
+        fs::write(&path, "one\ntwo\n").unwrap();
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Reloaded);
+        assert_eq!(document_text(&document), "one\rtwo\r");
+        assert!(!document.is_dirty());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Deleted);
+        assert!(document.is_dirty());
+    }
+
+    #[test]
+    fn a_dirty_file_changed_on_disk_is_a_conflict_to_merge() {
+        let dir = scratch_dir("merge");
+        let path = dir.join("a.txt");
+        fs::write(&path, "one\ntwo\nthree").unwrap();
+        let mut document = document(path.clone());
+        press(document.editor_mut(), "!", NONE);
+        fs::write(&path, "ONE ONE\ntwo\nthree").unwrap();
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Conflict);
+        assert_eq!(document_text(&document), "one\rtwo\rthree!");
+        assert!(document.can_merge());
+        assert_eq!(document.resolve_conflict(ConflictChoice::Merge).unwrap(), 0);
+        assert_eq!(document_text(&document), "ONE ONE\rtwo\rthree!");
+        assert!(document.is_dirty());
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ONE ONE\ntwo\nthree!");
+    }
+
+    #[test]
+    fn a_conflict_can_be_settled_either_way() {
+        let dir = scratch_dir("conflict-choices");
+        let path = dir.join("a.txt");
+        fs::write(&path, "base").unwrap();
+        let mut document = document(path.clone());
+        press(document.editor_mut(), "!", NONE);
+        fs::write(&path, "theirs").unwrap();
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Conflict);
+        document.resolve_conflict(ConflictChoice::KeepMine).unwrap();
+        assert_eq!(document_text(&document), "base!");
+        assert!(document.is_dirty());
+
+        fs::write(&path, "theirs again").unwrap();
+        assert_eq!(document.check_disk().unwrap(), DiskChange::Conflict);
+        document.resolve_conflict(ConflictChoice::Reload).unwrap();
+        assert_eq!(document_text(&document), "theirs again");
+        assert!(!document.is_dirty());
+    }
+
+    #[test]
+    fn large_files_are_mapped_and_reloaded_on_request() {
+        let dir = scratch_dir("large-file");
+        let path = dir.join("big.log");
+        let line = "a line of log é\r\n";
+        let lines = LARGE_FILE_SIZE / 10;
+        fs::write(&path, line.repeat(lines)).unwrap();
+        let mut document = document(path.clone());
+        assert_eq!(document_text(&document), "a line of log é\r".repeat(lines));
+        assert_eq!(document.format().line_ending, LineEnding::CrLf);
+        assert!(!document.can_merge());
+        document.set_recovery_dir(&dir.join("recovery"));
+        assert!(document.recovery.is_none());
+
+        fs::write(&path, line.repeat(lines + 1)).unwrap();
+        assert_eq!(document.check_disk().unwrap(), DiskChange::LargeFileChanged);
+        assert_eq!(document_text(&document), "a line of log é\r".repeat(lines));
+        document.reload_from_disk().unwrap();
+        assert_eq!(
+            document_text(&document),
+            "a line of log é\r".repeat(lines + 1)
+        );
+        assert!(!document.is_dirty());
+    }
+
+    #[test]
+    fn several_unsaved_documents_are_counted() {
+        let dir = scratch_dir("close-prompt-several");
+        let mut documents = vec![document(dir.join("a.txt")), document(dir.join("b.txt"))];
+        for document in &mut documents {
+            press(document.editor_mut(), "x", NONE);
+        }
+        let prompt = ClosePrompt::new(&documents).unwrap();
+        assert_eq!(prompt.unsaved, [0, 1]);
+        assert!(prompt.message.contains("2 documents"));
+    }
+}
@@ -0,0 +1,1512 @@
+use std::{
+    io::{self, Write},
+    mem,
+    ops::Range,
+};
+
+use crate::{
+    atlas::Atlas,
+    buffer::TextBuffer,
+    clipboard::{Clipboard, MemoryClipboard, clipboard_line_endings, normalize_line_endings},
+    command::{ControlCommand, KeyCommand, Modifiers, Motion, TextCommand},
+    history::{EditKind, EditOp, History},
+    keymap::{Key, Keymap},
+    kill_ring::KillRing,
+    layout::{
+        LineIndex, PageRange, build_cursor_verts, build_selection_verts, vertex_t, verts_from_text,
+    },
+    maths::{Float2, Float4},
+    selection::{Selection, Selections},
+    text::FontChain,
+    vim::{Vim, VimMode},
+};
+
+const SELECTION_COLOR: Float4 = Float4(0.7, 0.83, 1.0, 1.0);
+//most that gets laid out at once, a window of lines longer than this gets cut short
+#[cfg(not(test))]
+const MAX_WINDOW_CHARS: usize = 1 << 18;
+//small enough for tests to lay out long lines quickly
+#[cfg(test)]
+const MAX_WINDOW_CHARS: usize = 1 << 12;
+
+//platform independent input, the frontend translates its native events into these
+pub enum InputEvent {
+    KeyDown(Key, Modifiers),
+}
+
+//owns the text and cursor state and produces the vertex data the renderer draws,
+//knows nothing about windows or gpus
+pub struct Editor {
+    text_buffer: TextBuffer,
+    selections: Selections,
+    history: History<Selections>,
+    clipboard: Box<dyn Clipboard>,
+    kill_ring: KillRing,
+    //what the last yank or yank-pop inserted, which the next yank-pop replaces
+    yanked: Vec<Range<usize>>,
+    //edits are dropped, copying and moving around still work
+    readonly: bool,
+    //set-mark makes movement extend the selections, like emacs' transient mark mode
+    mark_active: bool,
+    //history state the file on disk has, anything else is unsaved
+    saved_state: u64,
+    save_requested: bool,
+    keymap: Keymap,
+    //modal layer in front of the keymap when vim mode is on
+    vim: Option<Vim>,
+    atlas: Atlas,
+    fonts: FontChain,
+    font_size: u32,
+    color: Float4,
+    text_box_width: f32,
+    cursor_start: Float2,
+    cursor: Float2,
+    cursor_counter: u32,
+    //of the chars in window, as is line_index
+    char_positions: Vec<Float2>,
+    line_index: LineIndex,
+    //what was laid out, the lines around the view, see layout_window
+    window: Range<usize>,
+    //logical line at the top of the view
+    scroll_line: usize,
+    //how far page up/down move, depends on the frontend's view
+    page_lines: usize,
+    text_verts: Vec<vertex_t>,
+    text_texs: Vec<Float2>,
+    page_ranges: Vec<PageRange>,
+}
+
+impl Editor {
+    pub fn new(
+        text_buffer: TextBuffer,
+        atlas: Atlas,
+        fonts: FontChain,
+        font_size: u32,
+        cursor_start: Float2,
+        text_box_width: f32,
+        color: Float4,
+    ) -> Editor {
+        let mut editor = Editor {
+            selections: Selections::single(Selection::caret(text_buffer.len_chars())),
+            text_buffer,
+            history: History::new(),
+            clipboard: Box::new(MemoryClipboard::new()),
+            kill_ring: KillRing::new(),
+            yanked: Vec::new(),
+            readonly: false,
+            mark_active: false,
+            saved_state: 0,
+            save_requested: false,
+            keymap: Keymap::default(),
+            vim: None,
+            atlas,
+            fonts,
+            font_size,
+            color,
+            text_box_width,
+            cursor_start,
+            cursor: cursor_start,
+            cursor_counter: 0,
+            char_positions: Vec::new(),
+            line_index: LineIndex::new(vec![0], 0),
+            window: 0..0,
+            scroll_line: 0,
+            page_lines: 20,
+            text_verts: Vec::new(),
+            text_texs: Vec::new(),
+            page_ranges: Vec::new(),
+        };
+        //trailing newline sentinel so the cursor always has a position at the end of the text
+        editor.text_buffer.push('\r');
+        editor.layout();
+        editor
+    }
+
+    //returns true if the text changed or scrolled and the text verts need to be re-uploaded
+    pub fn handle_input(&mut self, event: InputEvent) -> bool {
+        match event {
+            InputEvent::KeyDown(key, modifiers) => {
+                let Some(vim) = &mut self.vim else {
+                    return match self.handle_key(self.keymap.command_for(key, modifiers)) {
+                        Some(_) => {
+                            self.layout();
+                            true
+                        }
+                        None => self.follow_caret(),
+                    };
+                };
+                //a vim command undoes as one step, insert mode included
+                let commands = vim.commands_for(key, modifiers, &self.keymap);
+                self.history.begin_group();
+                let mut changed = false;
+                for command in commands {
+                    if self.handle_key(command).is_some() {
+                        //later commands in the sequence may need the new line index
+                        self.layout();
+                        changed = true;
+                    } else if self.follow_caret() {
+                        changed = true;
+                    }
+                }
+                match self.vim_mode() {
+                    Some(VimMode::Normal) => {
+                        self.history.end_group();
+                        self.normal_mode_carets();
+                    }
+                    Some(VimMode::Visual) => self.history.end_group(),
+                    _ => {}
+                }
+                changed
+            }
+        }
+    }
+
+    //vim's normal mode cursor sits on a char rather than selecting (undo can restore a
+    //selection), and it can only be on a newline if the line is empty
+    fn normal_mode_carets(&mut self) {
+        let text_buffer = &self.text_buffer;
+        self.selections.map(|selection| {
+            let head = selection.start();
+            let line_start = text_buffer.line_to_char(text_buffer.char_to_line(head));
+            match head > line_start && text_buffer.get_char(head) == Some('\r') {
+                true => Selection::caret(text_buffer.prev_grapheme_boundary(head)),
+                false => Selection::caret(head),
+            }
+        });
+    }
+
+    //returns the command if it changed the text
+    fn handle_key(&mut self, key_command: KeyCommand) -> Option<KeyCommand> {
+        //the trailing sentinel can never be selected or moved past
+        let last = self.text_buffer.len_chars() - 1;
+        //typing and deleting at carets coalesce, anything replacing a selection is its own step
+        let all_carets = self
+            .selections
+            .as_slice()
+            .iter()
+            .all(|selection| selection.is_empty());
+        match key_command {
+            KeyCommand::TC(text_command) => {
+                self.kill_ring.seal();
+                match text_command {
+                    TextCommand::Char(char) => {
+                        let kind = match all_carets {
+                            true => EditKind::Typing,
+                            false => EditKind::Other,
+                        };
+                        let text = char.to_string();
+                        self.edit_selections(kind, |_, selection| {
+                            Some((selection.range(), text.clone()))
+                        });
+                    }
+                    TextCommand::Newline => self
+                        .edit_selections(EditKind::Other, |_, selection| {
+                            Some((selection.range(), "\r".to_string()))
+                        }),
+                    TextCommand::Backspace => self.delete_selections(|text_buffer, head| {
+                        (head > 0).then(|| text_buffer.prev_grapheme_boundary(head)..head)
+                    }),
+                    TextCommand::Unknown => return None,
+                }
+                Some(KeyCommand::TC(text_command))
+            }
+            KeyCommand::CC(control_command) => {
+                self.history.seal();
+                //kills right after kills append, yank-pop only follows a yank
+                if !matches!(
+                    control_command,
+                    ControlCommand::Kill(_)
+                        | ControlCommand::KillLine
+                        | ControlCommand::KillRegion
+                        | ControlCommand::CopyRegion
+                        | ControlCommand::YankPop
+                ) {
+                    self.kill_ring.seal();
+                }
+                match control_command {
+                    ControlCommand::Move(motion) => {
+                        self.move_selections(motion, self.mark_active);
+                        None
+                    }
+                    ControlCommand::Select(motion) => {
+                        self.move_selections(motion, true);
+                        None
+                    }
+                    ControlCommand::SelectWord => {
+                        let text_buffer = &self.text_buffer;
+                        self.selections.map(|selection| {
+                            let word = text_buffer.word_at(selection.head.min(last));
+                            Selection::new(word.start, word.end.min(last))
+                        });
+                        None
+                    }
+                    //again on a selection of whole lines adds the next line
+                    ControlCommand::SelectLine => {
+                        let text_buffer = &self.text_buffer;
+                        self.selections.map(|selection| {
+                            let start_line = text_buffer.char_to_line(selection.start());
+                            let mut end_line = text_buffer.char_to_line(selection.end());
+                            if !selection.is_empty()
+                                && text_buffer.line_to_char(end_line) == selection.end()
+                            {
+                                end_line -= 1;
+                            }
+                            let whole_lines = !selection.is_empty()
+                                && text_buffer.line_to_char(start_line) == selection.start()
+                                && text_buffer.line_to_char(end_line + 1).min(last)
+                                    == selection.end();
+                            if whole_lines {
+                                end_line += 1;
+                            }
+                            let start = text_buffer.line_to_char(start_line);
+                            let end = text_buffer.line_to_char(end_line + 1).min(last);
+                            Selection::new(start, end)
+                        });
+                        None
+                    }
+                    ControlCommand::SelectAll => {
+                        self.selections = Selections::single(Selection::new(0, last));
+                        None
+                    }
+                    ControlCommand::DeleteForward => {
+                        self.delete_selections(|text_buffer, head| {
+                            Some(head..text_buffer.next_grapheme_boundary(head).min(last))
+                        });
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::DeleteWordBackward => {
+                        self.delete_selections(|text_buffer, head| {
+                            Some(text_buffer.prev_word_start(head)..head)
+                        });
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::DeleteWordForward => {
+                        self.delete_selections(|text_buffer, head| {
+                            Some(head..text_buffer.next_word_end(head).min(last))
+                        });
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::AddCursor(motion) => {
+                        let lines = match motion {
+                            Motion::Up => -1,
+                            _ => 1,
+                        };
+                        let primary = self.selections.primary();
+                        let (index, goal_x) = self.vertical_target(primary, lines);
+                        let mut caret = Selection::caret(index);
+                        caret.goal_x = goal_x;
+                        self.selections.add(caret);
+                        None
+                    }
+                    ControlCommand::CollapseCursors => {
+                        self.selections.collapse_to_primary();
+                        None
+                    }
+                    ControlCommand::Copy => {
+                        self.copy_selections();
+                        None
+                    }
+                    ControlCommand::Cut => {
+                        if !self.copy_selections() {
+                            return None;
+                        }
+                        self.edit_selections(EditKind::Other, |_, selection| {
+                            Some((selection.range(), String::new()))
+                        });
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::Paste => {
+                        let text = normalize_line_endings(&self.clipboard.read_text()?);
+                        self.paste_text(&text);
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::CopyLines => {
+                        self.copy_lines();
+                        None
+                    }
+                    ControlCommand::CutLines => {
+                        self.copy_lines();
+                        //the last line has no newline of its own, the one before it goes instead
+                        self.edit_selections(EditKind::Other, |_, selection| {
+                            let start = match selection.end() == last && selection.start() > 0 {
+                                true => selection.start() - 1,
+                                false => selection.start(),
+                            };
+                            Some((start..selection.end(), String::new()))
+                        });
+                        let text_buffer = &self.text_buffer;
+                        self.selections.map(|selection| {
+                            Selection::caret(
+                                text_buffer.line_to_char(text_buffer.char_to_line(selection.head)),
+                            )
+                        });
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::PasteLines { above, count } => {
+                        let mut text = normalize_line_endings(&self.clipboard.read_text()?);
+                        if !text.ends_with('\r') {
+                            text.push('\r');
+                        }
+                        self.paste_lines(&text.repeat(count), above);
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::GoToLine(line) => {
+                        self.go_to(line, 0);
+                        None
+                    }
+                    ControlCommand::SetMark => {
+                        self.selections
+                            .map(|selection| Selection::caret(selection.head));
+                        self.mark_active = true;
+                        None
+                    }
+                    ControlCommand::Cancel => {
+                        self.selections
+                            .map(|selection| Selection::caret(selection.head));
+                        self.mark_active = false;
+                        None
+                    }
+                    ControlCommand::Kill(motion) => {
+                        let ranges: Vec<_> = self
+                            .selections
+                            .as_slice()
+                            .iter()
+                            .map(|&selection| {
+                                let caret = Selection::caret(selection.head);
+                                let (target, _) = self.motion_target(caret, motion);
+                                target.min(selection.head)..target.max(selection.head)
+                            })
+                            .collect();
+                        let backward = matches!(
+                            motion,
+                            Motion::Left
+                                | Motion::LeftInLine
+                                | Motion::WordLeft
+                                | Motion::Up
+                                | Motion::PageUp
+                                | Motion::LineStart
+                                | Motion::ParagraphUp
+                                | Motion::ParagraphStart
+                                | Motion::DocStart
+                        );
+                        self.kill_ranges(ranges, backward)
+                            .then_some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::KillLine => {
+                        let ranges: Vec<_> = self
+                            .selections
+                            .as_slice()
+                            .iter()
+                            .map(|&selection| {
+                                let caret = Selection::caret(selection.head);
+                                let (end, _) = self.motion_target(caret, Motion::ParagraphEnd);
+                                match end == selection.head {
+                                    true => selection.head..(selection.head + 1).min(last),
+                                    false => selection.head..end,
+                                }
+                            })
+                            .collect();
+                        self.kill_ranges(ranges, false)
+                            .then_some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::KillRegion => {
+                        let ranges = self
+                            .selections
+                            .as_slice()
+                            .iter()
+                            .map(Selection::range)
+                            .collect();
+                        self.kill_ranges(ranges, false)
+                            .then_some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::CopyRegion => {
+                        if self.copy_selections() {
+                            let text = normalize_line_endings(&self.clipboard.read_text()?);
+                            self.kill_ring.push(text);
+                        }
+                        self.selections
+                            .map(|selection| Selection::caret(selection.head));
+                        self.mark_active = false;
+                        None
+                    }
+                    ControlCommand::Yank => {
+                        //text copied in another app since the last kill is yanked first
+                        if let Some(text) = self.clipboard.read_text() {
+                            self.kill_ring.push(normalize_line_endings(&text));
+                        }
+                        let text = self.kill_ring.yank()?.to_string();
+                        let over = self.selections.as_slice().iter().map(Selection::range);
+                        self.yank_text(&text, over.collect());
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::YankPop => {
+                        let text = self.kill_ring.yank_pop()?.to_string();
+                        let over = mem::take(&mut self.yanked);
+                        self.yank_text(&text, over);
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::Undo if self.readonly => None,
+                    ControlCommand::Redo if self.readonly => None,
+                    ControlCommand::Undo => {
+                        let selections = self.history.undo(&mut self.text_buffer)?;
+                        self.set_selections(selections);
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::Redo => {
+                        let selections = self.history.redo(&mut self.text_buffer)?;
+                        self.set_selections(selections);
+                        Some(KeyCommand::CC(control_command))
+                    }
+                    ControlCommand::Save => {
+                        self.save_requested = true;
+                        None
+                    }
+                    ControlCommand::Unknown => None,
+                }
+            }
+            KeyCommand::Unknown => None,
+        }
+    }
+
+    fn move_selections(&mut self, motion: Motion, extend: bool) {
+        let selections = self
+            .selections
+            .as_slice()
+            .iter()
+            .map(|&selection| match motion {
+                //left/right on a selection just collapses it to that side
+                Motion::Left if !extend && !selection.is_empty() => {
+                    Selection::caret(selection.start())
+                }
+                Motion::Right if !extend && !selection.is_empty() => {
+                    Selection::caret(selection.end())
+                }
+                _ => {
+                    let (head, goal_x) = self.motion_target(selection, motion);
+                    let mut selection = selection;
+                    selection.move_to(head, extend);
+                    selection.goal_x = goal_x;
+                    selection
+                }
+            })
+            .collect();
+        self.selections.replace_all(selections);
+    }
+
+    //where the head ends up, and the goal x to keep for vertical motions
+    fn motion_target(&self, selection: Selection, motion: Motion) -> (usize, Option<f32>) {
+        let last = self.text_buffer.len_chars() - 1;
+        let head = selection.head.min(last);
+        let line = self.line_index.line_range(self.line_index.line_of(head));
+        let page = self.page_lines as isize;
+        match motion {
+            Motion::Left => (self.text_buffer.prev_grapheme_boundary(head), None),
+            Motion::Right => (
+                self.text_buffer.next_grapheme_boundary(head).min(last),
+                None,
+            ),
+            Motion::Up => self.vertical_target(selection, -1),
+            Motion::Down => self.vertical_target(selection, 1),
+            Motion::PageUp => self.vertical_target(selection, -page),
+            Motion::PageDown => self.vertical_target(selection, page),
+            Motion::LineStart => (line.start, None),
+            //before the line's newline, or before the whitespace it wrapped at
+            Motion::LineEnd => ((line.end - 1).min(last), None),
+            Motion::DocStart => (0, None),
+            Motion::DocEnd => (last, None),
+            Motion::WordLeft => (self.text_buffer.prev_word_start(head), None),
+            Motion::WordRight => (self.text_buffer.next_word_end(head).min(last), None),
+            Motion::ParagraphUp => {
+                //to this paragraph's start, or the previous one's if already there
+                let paragraph = self.text_buffer.char_to_line(head);
+                let start = self.paragraph_start(paragraph);
+                match head > start || paragraph == 0 {
+                    true => (start, None),
+                    false => (self.paragraph_start(paragraph - 1), None),
+                }
+            }
+            Motion::ParagraphDown => {
+                //to just before this paragraph's newline, or the next one's if already there
+                let paragraph = self.text_buffer.char_to_line(head);
+                let end = self.paragraph_end(paragraph);
+                match head < end || paragraph + 1 >= self.text_buffer.len_lines() {
+                    true => (end, None),
+                    false => (self.paragraph_end(paragraph + 1), None),
+                }
+            }
+            Motion::ParagraphStart => (
+                self.paragraph_start(self.text_buffer.char_to_line(head)),
+                None,
+            ),
+            Motion::ParagraphEnd => (
+                self.paragraph_end(self.text_buffer.char_to_line(head)),
+                None,
+            ),
+            Motion::LeftInLine => {
+                match head == self.paragraph_start(self.text_buffer.char_to_line(head)) {
+                    true => (head, None),
+                    false => (self.text_buffer.prev_grapheme_boundary(head), None),
+                }
+            }
+            Motion::RightInLine => match self.text_buffer.char(head) {
+                '\r' => (head, None),
+                _ => (
+                    self.text_buffer.next_grapheme_boundary(head).min(last),
+                    None,
+                ),
+            },
+            Motion::NextWordStart => (self.text_buffer.next_word_start(head).min(last), None),
+            Motion::NextWordStartInLine => {
+                let paragraph_end = self.paragraph_end(self.text_buffer.char_to_line(head));
+                let word_start = self.text_buffer.next_word_start(head).min(last);
+                (word_start.min(paragraph_end.max(head)), None)
+            }
+            Motion::WordEnd => {
+                let after = (head + 1).min(last);
+                let end = self.text_buffer.next_word_end(after).saturating_sub(1);
+                (end.clamp(head, last), None)
+            }
+        }
+    }
+
+    fn paragraph_start(&self, paragraph: usize) -> usize {
+        self.text_buffer.line_to_char(paragraph)
+    }
+
+    //just before the paragraph's newline
+    fn paragraph_end(&self, paragraph: usize) -> usize {
+        let last = self.text_buffer.len_chars() - 1;
+        let next_start = self.text_buffer.line_to_char(paragraph + 1);
+        next_start.saturating_sub(1).min(last)
+    }
+
+    //char on the line `lines` away whose x is closest to the goal, moving past the first or
+    //last line goes to the start or end of the text
+    fn vertical_target(&self, selection: Selection, lines: isize) -> (usize, Option<f32>) {
+        let last = self.text_buffer.len_chars() - 1;
+        let head = selection.head.min(last);
+        let goal_x = selection
+            .goal_x
+            .or_else(|| self.position(head).map(|position| position.0))
+            .unwrap_or_default();
+        let line = self.line_index.line_of(head) as isize + lines;
+        //the window has a page to spare either side, so only running off the text's ends
+        //lands outside of it
+        if line < 0 && self.window.start == 0 {
+            return (0, Some(goal_x));
+        }
+        if line >= self.line_index.len() as isize && self.window.end == last + 1 {
+            return (last, Some(goal_x));
+        }
+        let line = line.clamp(0, self.line_index.len() as isize - 1) as usize;
+        let index = self
+            .line_index
+            .line_range(line)
+            .filter(|&index| self.text_buffer.is_grapheme_boundary(index))
+            .min_by(|&a, &b| {
+                let distance = |index: usize| {
+                    self.position(index)
+                        .map_or(f32::INFINITY, |position| (position.0 - goal_x).abs())
+                };
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap_or(head);
+        (index, Some(goal_x))
+    }
+
+    //replaces a range at every selection as one batch, edit returns the range (in the text as
+    //it is before any of the edits) and what goes there, or None to leave that selection alone
+    fn edit_selections<F: FnMut(&TextBuffer, Selection) -> Option<(Range<usize>, String)>>(
+        &mut self,
+        kind: EditKind,
+        mut edit: F,
+    ) {
+        if self.readonly {
+            return;
+        }
+        let before = self.selections.clone();
+        let mut edits: Vec<_> = before
+            .as_slice()
+            .iter()
+            .map(|&selection| edit(&self.text_buffer, selection))
+            .collect();
+        //e.g. backspace from two touching carets, the later one only gets what's left
+        let mut prev_end = 0;
+        for (range, _) in edits.iter_mut().flatten() {
+            range.start = range.start.max(prev_end).min(range.end);
+            prev_end = prev_end.max(range.end);
+        }
+
+        //applied back to front so every op's index is still valid when it runs
+        let mut ops = Vec::new();
+        for (range, text) in edits.iter().rev().flatten() {
+            if !range.is_empty() {
+                ops.push(EditOp::Remove {
+                    index: range.start,
+                    text: self.text_buffer.slice(range.clone()).to_string(),
+                });
+            }
+            if !text.is_empty() {
+                ops.push(EditOp::Insert {
+                    index: range.start,
+                    text: text.clone(),
+                });
+            }
+        }
+
+        let mut delta = 0isize;
+        let shift = |index: usize, delta: isize| (index as isize + delta) as usize;
+        let carets = before
+            .as_slice()
+            .iter()
+            .zip(&edits)
+            .map(|(selection, edit)| match edit {
+                Some((range, text)) => {
+                    let start = shift(range.start, delta);
+                    let len = text.chars().count();
+                    delta += len as isize - range.len() as isize;
+                    Selection::caret(start + len)
+                }
+                None => {
+                    Selection::new(shift(selection.anchor, delta), shift(selection.head, delta))
+                }
+            })
+            .collect();
+        let mut after = before.clone();
+        after.replace_all(carets);
+        self.history
+            .apply(ops, kind, &mut self.text_buffer, before, after.clone());
+        self.selections = after;
+        self.mark_active = false;
+    }
+
+    //deletes every selection, or for carets the range target picks from the head
+    fn delete_selections<F: Fn(&TextBuffer, usize) -> Option<Range<usize>>>(&mut self, target: F) {
+        let all_carets = self
+            .selections
+            .as_slice()
+            .iter()
+            .all(|selection| selection.is_empty());
+        let kind = match all_carets {
+            true => EditKind::Deleting,
+            false => EditKind::Other,
+        };
+        self.edit_selections(kind, |text_buffer, selection| match selection.is_empty() {
+            true => Some((target(text_buffer, selection.head)?, String::new())),
+            false => Some((selection.range(), String::new())),
+        });
+    }
+
+    //one line per cursor when the counts match, like after copying from them
+    fn paste_text(&mut self, text: &str) {
+        let mut pieces = self.paste_pieces(text).into_iter();
+        self.edit_selections(EditKind::Other, |_, selection| {
+            Some((selection.range(), pieces.next()?))
+        });
+    }
+
+    fn paste_pieces(&self, text: &str) -> Vec<String> {
+        let lines: Vec<&str> = text.split('\r').collect();
+        let distribute = self.selections.len() > 1 && lines.len() == self.selections.len();
+        match distribute {
+            true => lines.iter().map(|line| line.to_string()).collect(),
+            false => vec![text.to_string(); self.selections.len()],
+        }
+    }
+
+    //pastes over a range per selection and keeps where each piece went for yank-pop
+    fn yank_text(&mut self, text: &str, over: Vec<Range<usize>>) {
+        if self.readonly || over.len() != self.selections.len() {
+            return;
+        }
+        let pieces = self.paste_pieces(text);
+        let lens: Vec<usize> = pieces.iter().map(|piece| piece.chars().count()).collect();
+        let mut over = over.into_iter().zip(pieces);
+        self.edit_selections(EditKind::Other, |_, _| over.next());
+        self.yanked = self
+            .selections
+            .as_slice()
+            .iter()
+            .zip(lens)
+            .map(|(selection, len)| selection.head - len..selection.head)
+            .collect();
+    }
+
+    //lines ending in a newline, put on lines of their own above or below each caret's line,
+    //which leaves the caret at the start of the first one
+    fn paste_lines(&mut self, text: &str, above: bool) {
+        if self.readonly {
+            return;
+        }
+        //below goes before the line's newline instead of after it, the last line has none
+        let below = format!("\r{}", &text[..text.len() - 1]);
+        self.edit_selections(EditKind::Other, |text_buffer, selection| {
+            let line = text_buffer.char_to_line(selection.head);
+            match above {
+                true => {
+                    let start = text_buffer.line_to_char(line);
+                    Some((start..start, text.to_string()))
+                }
+                false => {
+                    let end = text_buffer.line_to_char(line + 1) - 1;
+                    Some((end..end, below.clone()))
+                }
+            }
+        });
+        let back = text.chars().count() - !above as usize;
+        self.selections
+            .map(|selection| Selection::caret(selection.head - back));
+    }
+
+    //removes a range per selection into the kill ring, which the clipboard mirrors,
+    //returns false if they were all empty
+    fn kill_ranges(&mut self, ranges: Vec<Range<usize>>, backward: bool) -> bool {
+        let texts: Vec<String> = ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| self.text_buffer.slice(range.clone()).to_string())
+            .collect();
+        if texts.is_empty() {
+            return false;
+        }
+        self.kill_ring.kill(&texts.join("\r"), backward);
+        if let Some(text) = self.kill_ring.latest() {
+            self.clipboard.write_text(&clipboard_line_endings(text));
+        }
+        let mut ranges = ranges.into_iter();
+        self.edit_selections(EditKind::Other, |_, _| {
+            Some((ranges.next()?, String::new()))
+        });
+        true
+    }
+
+    //the whole lines each selection covers, ending in a newline even on the last line
+    fn copy_lines(&mut self) {
+        let text: String = self
+            .selections
+            .as_slice()
+            .iter()
+            .map(|selection| {
+                let mut lines = self.text_buffer.slice(selection.range()).to_string();
+                if !lines.ends_with('\r') {
+                    lines.push('\r');
+                }
+                lines
+            })
+            .collect();
+        self.clipboard.write_text(&clipboard_line_endings(&text));
+    }
+
+    //selected text of every selection, one per line, returns false if nothing was selected
+    fn copy_selections(&mut self) -> bool {
+        let texts: Vec<String> = self
+            .selections
+            .as_slice()
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| self.text_buffer.slice(selection.range()).to_string())
+            .collect();
+        if texts.is_empty() {
+            return false;
+        }
+        self.clipboard
+            .write_text(&clipboard_line_endings(&texts.join("\r")));
+        true
+    }
+
+    fn set_selections(&mut self, mut selections: Selections) {
+        let last = self.text_buffer.len_chars() - 1;
+        selections
+            .map(|selection| Selection::new(selection.anchor.min(last), selection.head.min(last)));
+        self.selections = selections;
+    }
+
+    fn layout(&mut self) {
+        //where the caret would be on screen without scrolling, see below
+        let head = self.primary_head();
+        let head_y = self
+            .position(head)
+            .map(|position| position.1)
+            .or_else(|| (head >= self.window.end).then(|| self.view_bottom()));
+        let window = self.layout_window();
+        self.cursor = self.cursor_start;
+        let (text_verts, text_texs, char_positions, page_ranges, line_starts) = verts_from_text(
+            &mut self.cursor,
+            &self.text_buffer,
+            window.clone(),
+            self.text_box_width,
+            self.color,
+            &mut self.atlas,
+            &self.fonts,
+        );
+        //moved up so the top line is where the text starts, unless it starts before the
+        //window, which only happens inside a line too long to lay out whole, then the view
+        //follows the caret instead, keeping it where it was on screen as long as that's in view
+        let top = self.text_buffer.line_to_char(self.scroll_line);
+        let dy = match top < window.start {
+            true => {
+                let y = head_y
+                    .unwrap_or(self.cursor_start.1)
+                    .clamp(self.view_bottom(), self.cursor_start.1);
+                char_positions
+                    .get(head - window.start)
+                    .map_or(0.0, |position| y - position.1)
+            }
+            false => {
+                let top_y = char_positions
+                    .get(top.min(window.end) - window.start)
+                    .map_or(self.cursor.1, |position| position.1);
+                self.cursor_start.1 - top_y
+            }
+        };
+        self.text_verts = text_verts;
+        self.text_texs = text_texs;
+        self.page_ranges = page_ranges;
+        self.line_index = LineIndex::new(line_starts, window.end);
+        self.char_positions = char_positions;
+        self.window = window;
+        self.move_view(dy);
+    }
+
+    //moves everything laid out up by dy without laying it out again
+    fn move_view(&mut self, dy: f32) {
+        for vert in &mut self.text_verts {
+            vert.position.1 += dy;
+        }
+        for position in &mut self.char_positions {
+            position.1 += dy;
+        }
+        self.cursor.1 += dy;
+    }
+
+    //y of the last line that fits in the view
+    fn view_bottom(&self) -> f32 {
+        self.cursor_start.1 - (self.page_lines - 1) as f32 * self.atlas.line_height
+    }
+
+    //the lines from a page above the top one to a page below the bottom one, so page up and
+    //down always land on a line that's laid out, laying out the whole text every keystroke
+    //stops keeping up once it's more than a few pages, in very long lines it's cut short
+    //around the caret
+    fn layout_window(&mut self) -> Range<usize> {
+        let len = self.text_buffer.len_chars();
+        self.scroll_to_primary();
+        let head = self.primary_head();
+        let first = self.scroll_line.saturating_sub(self.page_lines);
+        let last = self.scroll_line + 2 * self.page_lines + 1;
+        let start = self
+            .text_buffer
+            .line_to_char(first)
+            .max(head.saturating_sub(MAX_WINDOW_CHARS / 2));
+        let end = match last < self.text_buffer.len_lines() {
+            true => self.text_buffer.line_to_char(last),
+            false => len,
+        }
+        .min(head + MAX_WINDOW_CHARS / 2);
+        //cut short ones still start and end between grapheme clusters
+        let start = self.text_buffer.prev_grapheme_boundary(start + 1);
+        let end = match end < len {
+            true => self.text_buffer.prev_grapheme_boundary(end + 1),
+            false => len,
+        };
+        start..end
+    }
+
+    //lays out again if moving the caret scrolled, or it's in a long line the window has to
+    //move along with it, true if it did
+    fn follow_caret(&mut self) -> bool {
+        let scroll_line = self.scroll_line;
+        self.scroll_to_primary();
+        let head = self.primary_head();
+        let top = self.text_buffer.line_to_char(self.scroll_line);
+        //ends of a window cut short in the middle of a line, well before the caret gets there
+        let margin = MAX_WINDOW_CHARS / 4;
+        let near_cut = (self.window.start > 0
+            && !self.is_line_start(self.window.start)
+            && head < self.window.start + margin)
+            || (self.window.end < self.text_buffer.len_chars()
+                && !self.is_line_start(self.window.end)
+                && head + margin > self.window.end);
+        if self.scroll_line == scroll_line && !near_cut {
+            //the view follows the caret when the top line starts before the window, see
+            //layout, moving what's already laid out is enough for that
+            let view = self.view_bottom()..=self.cursor_start.1;
+            match self.position(head) {
+                _ if top >= self.window.start => return false,
+                Some(position) if view.contains(&position.1) => return false,
+                Some(position) => {
+                    let y = position.1.clamp(*view.start(), *view.end());
+                    self.move_view(y - position.1);
+                    return true;
+                }
+                None => {}
+            }
+        }
+        self.layout();
+        true
+    }
+
+    fn primary_head(&self) -> usize {
+        let last = self.text_buffer.len_chars() - 1;
+        self.selections.primary().head.min(last)
+    }
+
+    fn is_line_start(&self, index: usize) -> bool {
+        let line = self.text_buffer.char_to_line(index);
+        self.text_buffer.line_to_char(line) == index
+    }
+
+    //scrolls by whole logical lines, so a caret below lines that wrap can end up past the
+    //bottom of the view
+    fn scroll_to_primary(&mut self) {
+        let last = self.text_buffer.len_chars() - 1;
+        let line = self
+            .text_buffer
+            .char_to_line(self.selections.primary().head.min(last));
+        if line < self.scroll_line {
+            self.scroll_line = line;
+        } else if line >= self.scroll_line + self.page_lines {
+            self.scroll_line = line + 1 - self.page_lines;
+        }
+    }
+
+    //where a char was laid out, None if it's outside the window
+    fn position(&self, index: usize) -> Option<Float2> {
+        index
+            .checked_sub(self.window.start)
+            .and_then(|index| self.char_positions.get(index))
+            .copied()
+    }
+
+    //advances the blink counter, call once per frame
+    pub fn cursor_verts(&mut self) -> Vec<vertex_t> {
+        //every caret blinks in step, so they all start from the same counter
+        let counter = self.cursor_counter;
+        let mut verts = Vec::new();
+        for selection in self.selections.as_slice() {
+            //carets off in a part of the text that isn't laid out
+            if !(self.window.start..=self.window.end).contains(&selection.head) {
+                continue;
+            }
+            self.cursor_counter = counter;
+            verts.append(&mut build_cursor_verts(
+                self.cursor,
+                selection.head - self.window.start,
+                &self.char_positions,
+                self.font_size,
+                &mut self.cursor_counter,
+            ));
+        }
+        verts
+    }
+
+    //highlight behind the selected chars, draw before the glyphs
+    pub fn selection_verts(&self) -> Vec<vertex_t> {
+        self.selections
+            .as_slice()
+            .iter()
+            .flat_map(|selection| {
+                let range = selection.range();
+                let clamp = |index: usize| index.clamp(self.window.start, self.window.end);
+                build_selection_verts(
+                    clamp(range.start) - self.window.start..clamp(range.end) - self.window.start,
+                    &self.char_positions,
+                    self.atlas.line_height,
+                    SELECTION_COLOR,
+                )
+            })
+            .collect()
+    }
+
+    pub fn text_verts(&self) -> &Vec<vertex_t> {
+        &self.text_verts
+    }
+
+    pub fn tex_pointers(&self) -> &Vec<Float2> {
+        &self.text_texs
+    }
+
+    //instances are grouped by atlas page, draw each range with that page's texture bound
+    pub fn page_ranges(&self) -> &[PageRange] {
+        &self.page_ranges
+    }
+
+    pub fn atlas(&self) -> &Atlas {
+        &self.atlas
+    }
+
+    pub fn text_buffer(&self) -> &TextBuffer {
+        &self.text_buffer
+    }
+
+    //height of the visible text, in the same units as the layout
+    pub fn set_page_height(&mut self, height: f32) {
+        self.page_lines = ((height / self.atlas.line_height) as usize).max(1);
+    }
+
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.clipboard = clipboard;
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    //true if the text differs from what was last saved, undoing back to it counts as saved
+    pub fn is_dirty(&self) -> bool {
+        self.history.state() != self.saved_state
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_state = self.history.state();
+    }
+
+    //replaces the whole text in one undoable step, e.g. with recovered unsaved changes
+    pub fn replace_text(&mut self, text: &str) {
+        let last = self.text_buffer.len_chars() - 1;
+        let old = self.text_buffer.slice(..last).to_string();
+        let text = normalize_line_endings(text);
+        let head = self.selections.primary().head.min(text.chars().count());
+        let mut ops = Vec::new();
+        if !old.is_empty() {
+            ops.push(EditOp::Remove {
+                index: 0,
+                text: old,
+            });
+        }
+        if !text.is_empty() {
+            ops.push(EditOp::Insert { index: 0, text });
+        }
+        let selections = Selections::single(Selection::caret(head));
+        self.history.seal();
+        self.history.apply(
+            ops,
+            EditKind::Other,
+            &mut self.text_buffer,
+            self.selections.clone(),
+            selections.clone(),
+        );
+        self.selections = selections;
+        self.mark_active = false;
+        self.layout();
+    }
+
+    //replaces the whole text without an undo step or any copy of the old text, e.g. for
+    //large files, takes it without the sentinel, as FileFormat::decode gives it
+    pub fn set_text_buffer(&mut self, mut text_buffer: TextBuffer) {
+        text_buffer.push('\r');
+        let head = self
+            .selections
+            .primary()
+            .head
+            .min(text_buffer.len_chars() - 1);
+        self.text_buffer = text_buffer;
+        self.history.clear();
+        self.selections = Selections::single(Selection::caret(head));
+        self.mark_active = false;
+        self.layout();
+    }
+
+    //keeps every edit applied from now on, undo and redo included, for take_edits
+    pub fn record_edits(&mut self) {
+        self.history.record_applied();
+    }
+
+    pub fn take_edits(&mut self) -> Vec<EditOp> {
+        self.history.take_applied()
+    }
+
+    //whether the save command ran since the last call
+    pub fn take_save_request(&mut self) -> bool {
+        mem::take(&mut self.save_requested)
+    }
+
+    //puts a single caret on a logical line, columns count grapheme clusters, both are 0 based
+    //and clamp to the text
+    pub fn go_to(&mut self, line: usize, column: usize) {
+        let last = self.text_buffer.len_chars() - 1;
+        let line = line.min(self.text_buffer.char_to_line(last));
+        let mut index = self.paragraph_start(line);
+        let end = self.paragraph_end(line);
+        for _ in 0..column {
+            if index >= end {
+                break;
+            }
+            index = self.text_buffer.next_grapheme_boundary(index);
+        }
+        self.selections = Selections::single(Selection::caret(index));
+        self.mark_active = false;
+        self.layout();
+    }
+
+    //starts in normal mode
+    pub fn set_vim_enabled(&mut self, enabled: bool) {
+        self.vim = enabled.then(Vim::new);
+    }
+
+    pub fn vim_mode(&self) -> Option<VimMode> {
+        self.vim.as_ref().map(Vim::mode)
+    }
+
+    //head of the primary selection
+    pub fn index_in_text(&self) -> usize {
+        self.selections.primary().head
+    }
+
+    pub fn selection(&self) -> Selection {
+        self.selections.primary()
+    }
+
+    pub fn selections(&self) -> &Selections {
+        &self.selections
+    }
+
+    //writes the text without the trailing sentinel
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let end = self.text_buffer.len_chars() - 1;
+        for chunk in self.text_buffer.slice(..end).chunks() {
+            writer.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use freetype::Library;
+
+    use super::*;
+    use crate::{
+        keymap::Preset,
+        text::loader::{FontLoader, FontSource},
+    };
+
+    pub(crate) const BACKSPACE: char = '\u{7f}';
+    pub(crate) const ESCAPE: char = '\u{1b}';
+    pub(crate) const NONE: Modifiers = Modifiers {
+        shift: false,
+        control: false,
+        option: false,
+        command: false,
+    };
+    pub(crate) const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ..NONE
+    };
+    pub(crate) const CMD: Modifiers = Modifiers {
+        command: true,
+        ..NONE
+    };
+    const CTRL: Modifiers = Modifiers {
+        control: true,
+        ..NONE
+    };
+    const OPTION: Modifiers = Modifiers {
+        option: true,
+        ..NONE
+    };
+
+    //with the embedded font, so it runs anywhere, the caret starts at the end like in the app
+    pub(crate) fn editor(text: &str) -> Editor {
+        let lib = Library::init().unwrap();
+        let font = FontLoader::new(&lib)
+            .load(&FontSource::Embedded, 50)
+            .unwrap();
+        let atlas = Atlas::new(&font.face).unwrap();
+        Editor::new(
+            TextBuffer::from(text),
+            atlas,
+            FontChain::new(font),
+            50,
+            Float2(-1000.0, 700.0),
+            2000.0,
+            Float4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub(crate) fn press(editor: &mut Editor, keys: &str, modifiers: Modifiers) {
+        for char in keys.chars() {
+            editor.handle_input(InputEvent::KeyDown(Key::from(char), modifiers));
+        }
+    }
+
+    pub(crate) fn press_keys(editor: &mut Editor, keys: &[Key], modifiers: Modifiers) {
+        for &key in keys {
+            editor.handle_input(InputEvent::KeyDown(key, modifiers));
+        }
+    }
+
+    pub(crate) fn text(editor: &Editor) -> String {
+        let mut text = Vec::new();
+        editor.write_to(&mut text).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    pub(crate) fn carets(editor: &Editor) -> Vec<(usize, usize)> {
+        editor
+            .selections()
+            .as_slice()
+            .iter()
+            .map(|selection| (selection.anchor, selection.head))
+            .collect()
+    }
+
+    #[test]
+    fn typing_inserts_at_the_caret() {
+        let mut editor = editor("world");
+        press_keys(&mut editor, &[Key::Left; 5], NONE);
+        press(&mut editor, "hello ", NONE);
+        assert_eq!(text(&editor), "hello world");
+        assert_eq!(carets(&editor), [(6, 6)]);
+    }
+
+    #[test]
+    fn backspace_deletes_a_whole_grapheme_cluster() {
+        let mut editor = editor("ae\u{301}");
+        press(&mut editor, &BACKSPACE.to_string(), NONE);
+        assert_eq!(text(&editor), "a");
+        press(&mut editor, &BACKSPACE.to_string().repeat(3), NONE);
+        assert_eq!(text(&editor), "");
+        assert_eq!(carets(&editor), [(0, 0)]);
+    }
+
+    #[test]
+    fn undo_and_redo_typing_as_one_step() {
+        let mut editor = editor("");
+        press(&mut editor, "abc", NONE);
+        press(&mut editor, "\r", NONE);
+        press(&mut editor, "de", NONE);
+        press(&mut editor, "z", CMD);
+        assert_eq!(text(&editor), "abc\r");
+        press(&mut editor, "zz", CMD);
+        assert_eq!(text(&editor), "");
+        press(&mut editor, "z", Modifiers { shift: true, ..CMD });
+        assert_eq!(text(&editor), "abc");
+        assert_eq!(carets(&editor), [(3, 3)]);
+    }
+
+    #[test]
+    fn undoing_back_to_the_save_point_is_clean() {
+        let mut editor = editor("text");
+        editor.mark_saved();
+        press(&mut editor, "!", NONE);
+        assert!(editor.is_dirty());
+        press(&mut editor, "z", CMD);
+        assert!(!editor.is_dirty());
+    }
+
+    #[test]
+    fn typing_replaces_the_selection() {
+        let mut editor = editor("hello world");
+        press_keys(&mut editor, &[Key::Left; 5], SHIFT);
+        assert_eq!(carets(&editor), [(11, 6)]);
+        press(&mut editor, "there", NONE);
+        assert_eq!(text(&editor), "hello there");
+        press(&mut editor, "a", CMD);
+        press(&mut editor, &BACKSPACE.to_string(), NONE);
+        assert_eq!(text(&editor), "");
+    }
+
+    #[test]
+    fn left_and_right_collapse_a_selection() {
+        let mut editor = editor("abcd");
+        press_keys(&mut editor, &[Key::Left; 2], SHIFT);
+        press_keys(&mut editor, &[Key::Left], NONE);
+        assert_eq!(carets(&editor), [(2, 2)]);
+        press_keys(&mut editor, &[Key::Right], SHIFT);
+        press_keys(&mut editor, &[Key::Right], NONE);
+        assert_eq!(carets(&editor), [(3, 3)]);
+    }
+
+    #[test]
+    fn up_and_down_keep_the_column() {
+        let mut editor = editor("abcdef\rab\rabcdef");
+        press_keys(&mut editor, &[Key::Up], NONE);
+        //the short line's end
+        assert_eq!(editor.index_in_text(), 9);
+        press_keys(&mut editor, &[Key::Up], NONE);
+        assert_eq!(editor.index_in_text(), 6);
+        press_keys(&mut editor, &[Key::Up], NONE);
+        assert_eq!(editor.index_in_text(), 0);
+        press_keys(&mut editor, &[Key::Down; 3], NONE);
+        assert_eq!(editor.index_in_text(), 16);
+    }
+
+    #[test]
+    fn motions_by_word_line_and_document() {
+        let mut editor = editor("one two\rthree");
+        press_keys(
+            &mut editor,
+            &[Key::Left],
+            Modifiers {
+                option: true,
+                ..NONE
+            },
+        );
+        assert_eq!(editor.index_in_text(), 8);
+        press_keys(&mut editor, &[Key::Up], CMD);
+        assert_eq!(editor.index_in_text(), 0);
+        press_keys(
+            &mut editor,
+            &[Key::Right],
+            Modifiers {
+                option: true,
+                ..NONE
+            },
+        );
+        assert_eq!(editor.index_in_text(), 3);
+        press_keys(&mut editor, &[Key::Right], CMD);
+        assert_eq!(editor.index_in_text(), 7);
+        press_keys(&mut editor, &[Key::Down], CMD);
+        assert_eq!(editor.index_in_text(), 13);
+    }
+
+    #[test]
+    fn extra_carets_all_type_and_collapse() {
+        let mut editor = editor("ab\rab");
+        let add_above = Modifiers {
+            option: true,
+            ..CMD
+        };
+        press_keys(&mut editor, &[Key::Up], add_above);
+        assert_eq!(carets(&editor), [(2, 2), (5, 5)]);
+        press(&mut editor, "x", NONE);
+        assert_eq!(text(&editor), "abx\rabx");
+        press(&mut editor, &ESCAPE.to_string(), NONE);
+        assert_eq!(carets(&editor), [(3, 3)]);
+        press(&mut editor, "z", CMD);
+        assert_eq!(text(&editor), "ab\rab");
+    }
+
+    #[test]
+    fn cut_and_paste_through_the_clipboard() {
+        let mut editor = editor("cut me");
+        press_keys(&mut editor, &[Key::Left; 2], SHIFT);
+        press(&mut editor, "x", CMD);
+        assert_eq!(text(&editor), "cut ");
+        press_keys(&mut editor, &[Key::Left; 4], NONE);
+        press(&mut editor, "v", CMD);
+        assert_eq!(text(&editor), "mecut ");
+    }
+
+    #[test]
+    fn the_clipboard_gets_newlines() {
+        let mut editor = editor("one\rtwo");
+        press(&mut editor, "a", CMD);
+        press(&mut editor, "c", CMD);
+        assert_eq!(editor.clipboard.read_text().as_deref(), Some("one\ntwo"));
+        press_keys(&mut editor, &[Key::Right], NONE);
+        press(&mut editor, "v", CMD);
+        assert_eq!(text(&editor), "one\rtwoone\rtwo");
+    }
+
+    #[test]
+    fn carets_deep_in_a_long_line_are_laid_out() {
+        let mut editor = editor(&"word ".repeat(MAX_WINDOW_CHARS / 4));
+        let end = editor.index_in_text();
+        assert!(end > MAX_WINDOW_CHARS);
+        assert!(editor.window.contains(&end));
+        assert!(!editor.cursor_verts().is_empty());
+        press_keys(&mut editor, &[Key::Up], NONE);
+        let up = editor.index_in_text();
+        assert!((end - 100..end).contains(&up));
+        press_keys(&mut editor, &[Key::Down], NONE);
+        assert_eq!(editor.index_in_text(), end);
+        press_keys(&mut editor, &[Key::Up], CMD);
+        assert_eq!(editor.index_in_text(), 0);
+        assert!(!editor.cursor_verts().is_empty());
+    }
+
+    fn emacs(text: &str) -> Editor {
+        let mut editor = editor(text);
+        editor.set_keymap(Keymap::preset(Preset::Emacs));
+        editor
+    }
+
+    #[test]
+    fn kills_in_a_row_make_one_entry() {
+        let mut editor = emacs("one\rtwo");
+        press(&mut editor, "<", OPTION);
+        press(&mut editor, "kk", CTRL);
+        assert_eq!(text(&editor), "two");
+        press(&mut editor, "e", CTRL);
+        press(&mut editor, "y", CTRL);
+        assert_eq!(text(&editor), "twoone\r");
+    }
+
+    #[test]
+    fn yank_pop_rotates_what_the_yank_inserted() {
+        let mut editor = emacs("a\rb");
+        press(&mut editor, "ak", CTRL);
+        press(&mut editor, "pak", CTRL);
+        assert_eq!(text(&editor), "\r");
+        press(&mut editor, "y", CTRL);
+        assert_eq!(text(&editor), "a\r");
+        press(&mut editor, "y", OPTION);
+        assert_eq!(text(&editor), "b\r");
+        press(&mut editor, "y", OPTION);
+        assert_eq!(text(&editor), "a\r");
+    }
+
+    #[test]
+    fn yank_pop_does_nothing_after_typing() {
+        let mut editor = emacs("foo\rbar");
+        press(&mut editor, "aky", CTRL);
+        press(&mut editor, "X", NONE);
+        press(&mut editor, "y", OPTION);
+        assert_eq!(text(&editor), "foo\rbarX");
+    }
+
+    #[test]
+    fn the_window_is_centred_on_a_caret_in_a_long_line() {
+        let mut editor = editor(&"word ".repeat(MAX_WINDOW_CHARS / 2));
+        let len = editor.text_buffer.len_chars();
+        let middle = len / 2;
+        editor.go_to(0, middle);
+        let head = editor.index_in_text();
+        assert_eq!(head, middle);
+        let half = MAX_WINDOW_CHARS / 2;
+        assert!(editor.window.start.abs_diff(head - half) <= 1);
+        assert!(editor.window.end.abs_diff(head + half) <= 1);
+        assert!(!editor.cursor_verts().is_empty());
+
+        //moving within the window keeps it, nearing a cut end moves it along
+        press_keys(&mut editor, &[Key::Right; 10], NONE);
+        assert!(editor.window.start.abs_diff(head - half) <= 1);
+        editor.go_to(0, head + half - 10);
+        let head = editor.index_in_text();
+        assert!(editor.window.start.abs_diff(head - half) <= 1);
+        assert!(editor.window.contains(&head));
+    }
+
+    #[test]
+    fn only_the_lines_around_the_view_are_laid_out() {
+        let mut editor = editor(&"line\r".repeat(1000));
+        editor.set_page_height(10.0 * editor.atlas.line_height);
+        editor.go_to(500, 0);
+        let (first, last) = (
+            editor.text_buffer.char_to_line(editor.window.start),
+            editor.text_buffer.char_to_line(editor.window.end),
+        );
+        assert!(first < 500 && 500 < last);
+        assert!(last - first <= 4 * editor.page_lines);
+    }
+
+    #[test]
+    fn read_only_ignores_edits() {
+        let mut editor = editor("fixed");
+        editor.set_readonly(true);
+        press(&mut editor, "abc", NONE);
+        press(&mut editor, &BACKSPACE.to_string(), NONE);
+        assert_eq!(text(&editor), "fixed");
+        assert!(!editor.is_dirty());
+    }
+}
@@ -0,0 +1,453 @@
+use std::{
+    fmt,
+    io::{self, ErrorKind, Write},
+    str::FromStr,
+};
+
+use crate::buffer::{TextBuffer, TextBufferBuilder};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+//how much of a file without a bom is looked at to guess utf-16
+const SNIFF_LEN: usize = 4096;
+//files are decoded this many bytes at a time, so there's never a second copy of a big one
+const PIECE_LEN: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+//how a file stores its text, the buffer only ever has utf-8 and \r newlines,
+//so this is what gets undone on load and redone on save
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub encoding: Encoding,
+    //whether the file starts with a byte order mark
+    pub bom: bool,
+    //the file has other line endings besides line_ending, saving makes them all line_ending
+    pub mixed_line_endings: bool,
+}
+
+impl FileFormat {
+    pub fn new(line_ending: LineEnding, encoding: Encoding, bom: bool) -> Self {
+        Self {
+            line_ending,
+            encoding,
+            bom,
+            mixed_line_endings: false,
+        }
+    }
+
+    //text with \r newlines and the format it came in, files with mixed line endings
+    //get whichever one they have most of and are marked as mixed
+    pub fn decode(bytes: &[u8]) -> io::Result<(TextBuffer, FileFormat)> {
+        let (encoding, bom) = Encoding::detect(bytes);
+        let mut bytes = match bom {
+            true => &bytes[encoding.bom().len()..],
+            false => bytes,
+        };
+        let mut builder = TextBufferBuilder::new();
+        let mut newlines = Newlines::default();
+        let mut normalized = String::new();
+        while !bytes.is_empty() {
+            let end = encoding.piece_end(bytes);
+            normalized.clear();
+            newlines.normalize(&encoding.decode(&bytes[..end])?, &mut normalized);
+            builder.append(&normalized);
+            bytes = &bytes[end..];
+        }
+        let format = FileFormat {
+            mixed_line_endings: newlines.is_mixed(),
+            ..FileFormat::new(newlines.most_common(), encoding, bom)
+        };
+        Ok((builder.finish(), format))
+    }
+
+    //what saving in this format writes, one kind of line ending throughout
+    pub fn normalized(self) -> FileFormat {
+        FileFormat {
+            mixed_line_endings: false,
+            ..self
+        }
+    }
+
+    //this format with whatever was asked for instead, utf-16 gets a bom unless told otherwise
+    //as that's how most programs tell it apart
+    pub fn convert(
+        self,
+        line_ending: Option<LineEnding>,
+        encoding: Option<Encoding>,
+        bom: Option<bool>,
+    ) -> FileFormat {
+        let encoding_changed = encoding.is_some_and(|encoding| encoding != self.encoding);
+        let encoding = encoding.unwrap_or(self.encoding);
+        let bom = bom.unwrap_or(match encoding_changed {
+            true => encoding != Encoding::Utf8,
+            false => self.bom,
+        });
+        FileFormat::new(line_ending.unwrap_or(self.line_ending), encoding, bom)
+    }
+
+    //text as the buffer has it, so \r newlines only
+    pub fn write<'a, I, W>(&self, chunks: I, mut writer: W) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'a str>,
+        W: Write,
+    {
+        if self.bom {
+            writer.write_all(self.encoding.bom())?;
+        }
+        let newline = self.line_ending.as_str();
+        let mut bytes = Vec::new();
+        for chunk in chunks {
+            bytes.clear();
+            for (index, line) in chunk.split('\r').enumerate() {
+                if index > 0 {
+                    self.encoding.encode(newline, &mut bytes);
+                }
+                self.encoding.encode(line, &mut bytes);
+            }
+            writer.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+//counts each kind of line ending while turning them all into \r, a \r at the end of one
+//piece of text waits for the next to see if it's half of a \r\n
+#[derive(Default)]
+struct Newlines {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+    pending_cr: bool,
+}
+
+impl Newlines {
+    fn normalize(&mut self, text: &str, normalized: &mut String) {
+        for char in text.chars() {
+            match char {
+                '\n' if self.pending_cr => {
+                    self.crlf += 1;
+                    self.pending_cr = false;
+                    continue;
+                }
+                '\n' => {
+                    self.lf += 1;
+                    normalized.push('\r');
+                    continue;
+                }
+                _ if self.pending_cr => {
+                    self.cr += 1;
+                    self.pending_cr = false;
+                }
+                _ => {}
+            }
+            self.pending_cr = char == '\r';
+            normalized.push(char);
+        }
+    }
+
+    fn is_mixed(&self) -> bool {
+        let cr = self.cr + self.pending_cr as usize;
+        [self.lf, self.crlf, cr]
+            .iter()
+            .filter(|&&count| count > 0)
+            .count()
+            > 1
+    }
+
+    //lf for text without any
+    fn most_common(&self) -> LineEnding {
+        let cr = self.cr + self.pending_cr as usize;
+        if self.crlf > self.lf && self.crlf >= cr {
+            LineEnding::CrLf
+        } else if cr > self.lf && cr > self.crlf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+impl Encoding {
+    //and whether there's a bom, which decides it if there is, otherwise text full of zero bytes
+    //on one side is taken to be utf-16, as no utf-8 text file has those
+    pub fn detect(bytes: &[u8]) -> (Encoding, bool) {
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            if bytes.starts_with(encoding.bom()) {
+                return (encoding, true);
+            }
+        }
+        let sniffed = &bytes[..bytes.len().min(SNIFF_LEN)];
+        let zeros_at = |parity| {
+            sniffed
+                .iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|&&byte| byte == 0)
+                .count()
+        };
+        let (even_zeros, odd_zeros) = (zeros_at(0), zeros_at(1));
+        let pairs = sniffed.len() / 2;
+        if bytes.len().is_multiple_of(2) && pairs > 0 {
+            if odd_zeros * 4 > pairs && even_zeros == 0 {
+                return (Encoding::Utf16Le, false);
+            }
+            if even_zeros * 4 > pairs && odd_zeros == 0 {
+                return (Encoding::Utf16Be, false);
+            }
+        }
+        (Encoding::Utf8, false)
+    }
+
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => UTF8_BOM,
+            Encoding::Utf16Le => UTF16LE_BOM,
+            Encoding::Utf16Be => UTF16BE_BOM,
+        }
+    }
+
+    //bytes after the bom, if there is one
+    pub fn decode(self, bytes: &[u8]) -> io::Result<String> {
+        let invalid = || io::Error::new(ErrorKind::InvalidData, format!("file isn't valid {self}"));
+        if self == Encoding::Utf8 {
+            return String::from_utf8(bytes.to_vec()).map_err(|_| invalid());
+        }
+        if !bytes.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let units = bytes
+            .chunks_exact(2)
+            .map(|pair| self.unit([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|_| invalid())
+    }
+
+    //where to cut off the next piece to decode without splitting a char, invalid text is
+    //cut anywhere and left for decode to reject
+    fn piece_end(self, bytes: &[u8]) -> usize {
+        if bytes.len() <= PIECE_LEN {
+            return bytes.len();
+        }
+        match self {
+            //back to the first byte of a char, which is never 10xxxxxx
+            Encoding::Utf8 => (PIECE_LEN - 3..=PIECE_LEN)
+                .rev()
+                .find(|&index| bytes[index] & 0xC0 != 0x80)
+                .unwrap_or(PIECE_LEN),
+            //not between the two halves of a surrogate pair
+            _ => match self.unit([bytes[PIECE_LEN - 2], bytes[PIECE_LEN - 1]]) {
+                0xD800..=0xDBFF => PIECE_LEN - 2,
+                _ => PIECE_LEN,
+            },
+        }
+    }
+
+    //utf-16 code unit in this byte order
+    fn unit(self, pair: [u8; 2]) -> u16 {
+        match self {
+            Encoding::Utf16Be => u16::from_be_bytes(pair),
+            _ => u16::from_le_bytes(pair),
+        }
+    }
+
+    fn encode(self, text: &str, bytes: &mut Vec<u8>) {
+        match self {
+            Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+        }
+    }
+}
+
+//names as in --line-ending and --encoding
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEnding::Lf => "lf",
+            LineEnding::CrLf => "crlf",
+            LineEnding::Cr => "cr",
+        })
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" | "unix" => Ok(LineEnding::Lf),
+            "crlf" | "windows" => Ok(LineEnding::CrLf),
+            "cr" | "mac" => Ok(LineEnding::Cr),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+        })
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn written(text_buffer: &TextBuffer, format: FileFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let text = text_buffer.to_string();
+        format.write([text.as_str()], &mut bytes).unwrap();
+        bytes
+    }
+
+    //decodes to \r newlines and writes back the same bytes
+    fn round_trip(bytes: &[u8], text: &str, format: FileFormat) {
+        let (text_buffer, decoded) = FileFormat::decode(bytes).unwrap();
+        assert_eq!(text_buffer.to_string(), text);
+        assert_eq!(decoded, format);
+        assert_eq!(written(&text_buffer, decoded), bytes);
+    }
+
+    #[test]
+    fn boms_decide_the_encoding() {
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFa"), (Encoding::Utf8, true));
+        assert_eq!(Encoding::detect(b"\xFF\xFEa\0"), (Encoding::Utf16Le, true));
+        assert_eq!(Encoding::detect(b"\xFE\xFF\0a"), (Encoding::Utf16Be, true));
+    }
+
+    #[test]
+    fn utf16_without_a_bom_is_told_by_its_zero_bytes() {
+        assert_eq!(Encoding::detect(b"a\0b\0"), (Encoding::Utf16Le, false));
+        assert_eq!(Encoding::detect(b"\0a\0b"), (Encoding::Utf16Be, false));
+        assert_eq!(Encoding::detect(b"ab"), (Encoding::Utf8, false));
+        assert_eq!(Encoding::detect(b"a\0b"), (Encoding::Utf8, false));
+        assert_eq!(Encoding::detect(b""), (Encoding::Utf8, false));
+    }
+
+    #[test]
+    fn line_endings_round_trip() {
+        let format = |line_ending| FileFormat::new(line_ending, Encoding::Utf8, false);
+        round_trip(b"a\nb\n", "a\rb\r", format(LineEnding::Lf));
+        round_trip(b"a\r\nb\r\n", "a\rb\r", format(LineEnding::CrLf));
+        round_trip(b"a\rb\r", "a\rb\r", format(LineEnding::Cr));
+        round_trip(b"no newline", "no newline", format(LineEnding::Lf));
+    }
+
+    #[test]
+    fn encodings_round_trip_with_and_without_a_bom() {
+        let utf8 = |bom| FileFormat::new(LineEnding::Lf, Encoding::Utf8, bom);
+        round_trip("é\n".as_bytes(), "é\r", utf8(false));
+        round_trip(b"\xEF\xBB\xBF\xC3\xA9\n", "é\r", utf8(true));
+
+        let le = [UTF16LE_BOM, &utf16le("é😀\r\n")].concat();
+        let format = FileFormat::new(LineEnding::CrLf, Encoding::Utf16Le, true);
+        round_trip(&le, "é😀\r", format);
+        let be: Vec<u8> = "é😀\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let be = [UTF16BE_BOM, &be].concat();
+        let format = FileFormat::new(LineEnding::CrLf, Encoding::Utf16Be, true);
+        round_trip(&be, "é😀\r", format);
+    }
+
+    #[test]
+    fn mixed_line_endings_are_flagged() {
+        let (text_buffer, format) = FileFormat::decode(b"a\rb\nc\n").unwrap();
+        assert_eq!(text_buffer.to_string(), "a\rb\rc\r");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert!(format.mixed_line_endings);
+        assert!(!format.normalized().mixed_line_endings);
+        assert_eq!(written(&text_buffer, format), b"a\nb\nc\n");
+        let (_, format) = FileFormat::decode(b"a\r\nb\r\nc\n").unwrap();
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.mixed_line_endings);
+    }
+
+    #[test]
+    fn invalid_text_is_an_error() {
+        assert!(FileFormat::decode(b"\xC3").is_err());
+        assert!(FileFormat::decode(b"\xFF\xFEa").is_err());
+        //a lone high surrogate
+        assert!(FileFormat::decode(b"\xFF\xFE\x00\xD8").is_err());
+    }
+
+    #[test]
+    fn pieces_end_between_chars() {
+        let mut utf8 = vec![b'a'; PIECE_LEN - 1];
+        utf8.extend_from_slice("é".as_bytes());
+        assert_eq!(Encoding::Utf8.piece_end(&utf8), PIECE_LEN - 1);
+        assert_eq!(
+            Encoding::Utf8.piece_end(&utf8[..PIECE_LEN - 1]),
+            PIECE_LEN - 1
+        );
+
+        let pair = "a".repeat(PIECE_LEN / 2 - 1) + "😀";
+        assert_eq!(Encoding::Utf16Le.piece_end(&utf16le(&pair)), PIECE_LEN - 2);
+        let unsplit = "a".repeat(PIECE_LEN / 2) + "😀";
+        assert_eq!(Encoding::Utf16Le.piece_end(&utf16le(&unsplit)), PIECE_LEN);
+    }
+
+    #[test]
+    fn chars_and_newlines_across_pieces_decode_whole() {
+        let text = "a".repeat(PIECE_LEN / 2 - 1) + "😀b";
+        let (text_buffer, _) = FileFormat::decode(&utf16le(&text)).unwrap();
+        assert_eq!(text_buffer.to_string(), text);
+
+        let mut crlf = vec![b'a'; PIECE_LEN - 1];
+        crlf.extend_from_slice(b"\r\nb");
+        let (text_buffer, format) = FileFormat::decode(&crlf).unwrap();
+        assert_eq!(text_buffer.len_chars(), PIECE_LEN + 1);
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(!format.mixed_line_endings);
+    }
+}
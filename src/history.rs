@@ -0,0 +1,383 @@
+use crate::buffer::TextBuffer;
+
+//a single change to the buffer, removals keep their text so they can be inverted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditOp {
+    Insert { index: usize, text: String },
+    Remove { index: usize, text: String },
+}
+
+impl EditOp {
+    pub fn inverse(&self) -> EditOp {
+        match self {
+            EditOp::Insert { index, text } => EditOp::Remove {
+                index: *index,
+                text: text.clone(),
+            },
+            EditOp::Remove { index, text } => EditOp::Insert {
+                index: *index,
+                text: text.clone(),
+            },
+        }
+    }
+
+    pub fn apply(&self, text_buffer: &mut TextBuffer) {
+        match self {
+            EditOp::Insert { index, text } => text_buffer.insert(*index, text),
+            EditOp::Remove { index, text } => {
+                text_buffer.remove(*index..*index + text.chars().count())
+            }
+        }
+    }
+}
+
+//only edits of the same kind get merged into one undo step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Typing,
+    Deleting,
+    //never merged, e.g. newlines or anything replacing a range
+    Other,
+}
+
+//one undo step, cursor state is whatever the editor needs to put the caret back
+struct EditGroup<C> {
+    //changes whenever the group does, see state
+    id: u64,
+    ops: Vec<EditOp>,
+    kind: EditKind,
+    cursor_before: C,
+    cursor_after: C,
+}
+
+pub struct History<C> {
+    undo_stack: Vec<EditGroup<C>>,
+    redo_stack: Vec<EditGroup<C>>,
+    //cleared by anything that isn't an edit, e.g. moving the cursor, so the next edit starts a new group
+    can_merge: bool,
+    //undo stack length when begin_group was called
+    group_start: Option<usize>,
+    next_id: u64,
+    //every op applied to the buffer in order, undos and redos included, see record_applied
+    applied: Option<Vec<EditOp>>,
+}
+
+impl<C: Clone> History<C> {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            can_merge: false,
+            group_start: None,
+            next_id: 1,
+            applied: None,
+        }
+    }
+
+    //applies the ops in order as one batch, e.g. the same keystroke at every cursor,
+    //and records them, merging into the last group if they continue it
+    pub fn apply(
+        &mut self,
+        ops: Vec<EditOp>,
+        kind: EditKind,
+        text_buffer: &mut TextBuffer,
+        cursor_before: C,
+        cursor_after: C,
+    ) {
+        if ops.is_empty() {
+            return;
+        }
+        for op in &ops {
+            op.apply(text_buffer);
+        }
+        if let Some(applied) = &mut self.applied {
+            applied.extend_from_slice(&ops);
+        }
+        self.redo_stack.clear();
+        if self.can_merge
+            && kind != EditKind::Other
+            && let Some(group) = self.undo_stack.last_mut()
+            && group.kind == kind
+            && merge_batch(&mut group.ops, &ops)
+        {
+            group.cursor_after = cursor_after;
+            group.id = self.next_id;
+            self.next_id += 1;
+            return;
+        }
+        self.undo_stack.push(EditGroup {
+            id: self.next_id,
+            ops,
+            kind,
+            cursor_before,
+            cursor_after,
+        });
+        self.can_merge = kind != EditKind::Other;
+        self.next_id += 1;
+    }
+
+    pub fn seal(&mut self) {
+        self.can_merge = false;
+    }
+
+    //everything applied until end_group undoes as one step, e.g. a whole vim command,
+    //does nothing if a group is already open
+    pub fn begin_group(&mut self) {
+        if self.group_start.is_none() {
+            self.group_start = Some(self.undo_stack.len());
+        }
+    }
+
+    pub fn end_group(&mut self) {
+        let Some(start) = self.group_start.take() else {
+            return;
+        };
+        if self.undo_stack.len() <= start + 1 {
+            return;
+        }
+        let mut groups = self.undo_stack.split_off(start).into_iter();
+        let Some(mut group) = groups.next() else {
+            return;
+        };
+        for next in groups {
+            group.ops.extend(next.ops);
+            group.cursor_after = next.cursor_after;
+            //same text as the last group led to
+            group.id = next.id;
+        }
+        group.kind = EditKind::Other;
+        self.undo_stack.push(group);
+        self.can_merge = false;
+    }
+
+    //returns the cursor from before the undone edit
+    pub fn undo(&mut self, text_buffer: &mut TextBuffer) -> Option<C> {
+        let group = self.undo_stack.pop()?;
+        for op in group.ops.iter().rev() {
+            let inverse = op.inverse();
+            inverse.apply(text_buffer);
+            if let Some(applied) = &mut self.applied {
+                applied.push(inverse);
+            }
+        }
+        let cursor = group.cursor_before.clone();
+        self.redo_stack.push(group);
+        self.can_merge = false;
+        self.group_start = None;
+        Some(cursor)
+    }
+
+    //returns the cursor from after the redone edit
+    pub fn redo(&mut self, text_buffer: &mut TextBuffer) -> Option<C> {
+        let group = self.redo_stack.pop()?;
+        for op in &group.ops {
+            op.apply(text_buffer);
+        }
+        if let Some(applied) = &mut self.applied {
+            applied.extend_from_slice(&group.ops);
+        }
+        let cursor = group.cursor_after.clone();
+        self.undo_stack.push(group);
+        self.can_merge = false;
+        self.group_start = None;
+        Some(cursor)
+    }
+
+    //identifies the text the history has led to, undoing back to a state gives the same id again,
+    //0 is the text before any edit
+    pub fn state(&self) -> u64 {
+        self.undo_stack.last().map_or(0, |group| group.id)
+    }
+
+    //starts keeping the ops applied from now on for take_applied, e.g. to journal them
+    pub fn record_applied(&mut self) {
+        self.applied.get_or_insert_with(Vec::new);
+    }
+
+    pub fn take_applied(&mut self) -> Vec<EditOp> {
+        self.applied
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    //forgets every edit, e.g. once the text was swapped for one they don't apply to,
+    //state goes back to 0
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.can_merge = false;
+        self.group_start = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
+
+impl<C: Clone> Default for History<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//a single op is folded into the last one if it continues it, batches from several cursors
+//are just appended since undoing them in reverse order is all that matters
+fn merge_batch(ops: &mut Vec<EditOp>, batch: &[EditOp]) -> bool {
+    match batch {
+        [op] => ops.last_mut().is_some_and(|last| merge_ops(last, op)),
+        _ => {
+            ops.extend_from_slice(batch);
+            true
+        }
+    }
+}
+
+//typing after the last insert, or deleting right before (backspace) or at (delete) the last removal
+fn merge_ops(last: &mut EditOp, op: &EditOp) -> bool {
+    match (last, op) {
+        (
+            EditOp::Insert { index, text },
+            EditOp::Insert {
+                index: next_index,
+                text: next_text,
+            },
+        ) if *index + text.chars().count() == *next_index => {
+            text.push_str(next_text);
+            true
+        }
+        (
+            EditOp::Remove { index, text },
+            EditOp::Remove {
+                index: next_index,
+                text: next_text,
+            },
+        ) if *next_index + next_text.chars().count() == *index => {
+            text.insert_str(0, next_text);
+            *index = *next_index;
+            true
+        }
+        (
+            EditOp::Remove { index, text },
+            EditOp::Remove {
+                index: next_index,
+                text: next_text,
+            },
+        ) if *next_index == *index => {
+            text.push_str(next_text);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(index: usize, text: &str) -> Vec<EditOp> {
+        vec![EditOp::Insert {
+            index,
+            text: text.to_string(),
+        }]
+    }
+
+    fn remove(index: usize, text: &str) -> Vec<EditOp> {
+        vec![EditOp::Remove {
+            index,
+            text: text.to_string(),
+        }]
+    }
+
+    #[test]
+    fn contiguous_typing_undoes_as_one_step() {
+        let mut text_buffer = TextBuffer::new();
+        let mut history = History::new();
+        history.apply(insert(0, "a"), EditKind::Typing, &mut text_buffer, 0, 1);
+        history.apply(insert(1, "b"), EditKind::Typing, &mut text_buffer, 1, 2);
+        //not where the last insert ended
+        history.apply(insert(0, "c"), EditKind::Typing, &mut text_buffer, 2, 1);
+        assert_eq!(text_buffer.to_string(), "cab");
+        assert_eq!(history.undo(&mut text_buffer), Some(2));
+        assert_eq!(history.undo(&mut text_buffer), Some(0));
+        assert_eq!(text_buffer.to_string(), "");
+        assert!(!history.can_undo());
+        assert_eq!(history.redo(&mut text_buffer), Some(2));
+        assert_eq!(text_buffer.to_string(), "ab");
+        assert!(history.can_redo());
+    }
+
+    #[test]
+    fn backspace_and_delete_merge() {
+        let mut text_buffer = TextBuffer::from("abcd");
+        let mut history = History::new();
+        history.apply(remove(2, "c"), EditKind::Deleting, &mut text_buffer, 3, 2);
+        history.apply(remove(1, "b"), EditKind::Deleting, &mut text_buffer, 2, 1);
+        history.apply(remove(1, "d"), EditKind::Deleting, &mut text_buffer, 1, 1);
+        assert_eq!(text_buffer.to_string(), "a");
+        history.undo(&mut text_buffer);
+        assert_eq!(text_buffer.to_string(), "abcd");
+    }
+
+    #[test]
+    fn seal_and_other_edits_start_a_new_step() {
+        let mut text_buffer = TextBuffer::new();
+        let mut history = History::new();
+        history.apply(insert(0, "a"), EditKind::Typing, &mut text_buffer, 0, 1);
+        history.seal();
+        history.apply(insert(1, "b"), EditKind::Typing, &mut text_buffer, 1, 2);
+        history.apply(insert(2, "\r"), EditKind::Other, &mut text_buffer, 2, 3);
+        history.apply(insert(3, "c"), EditKind::Typing, &mut text_buffer, 3, 4);
+        for expected in ["ab\r", "ab", "a", ""] {
+            history.undo(&mut text_buffer);
+            assert_eq!(text_buffer.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn groups_undo_together() {
+        let mut text_buffer = TextBuffer::new();
+        let mut history = History::new();
+        history.begin_group();
+        history.apply(insert(0, "a"), EditKind::Other, &mut text_buffer, 0, 1);
+        history.apply(insert(1, "b"), EditKind::Other, &mut text_buffer, 1, 2);
+        history.end_group();
+        history.undo(&mut text_buffer);
+        assert_eq!(text_buffer.to_string(), "");
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn state_returns_to_the_same_id() {
+        let mut text_buffer = TextBuffer::new();
+        let mut history = History::new();
+        assert_eq!(history.state(), 0);
+        history.apply(insert(0, "a"), EditKind::Other, &mut text_buffer, 0, 1);
+        let saved = history.state();
+        history.apply(insert(1, "b"), EditKind::Other, &mut text_buffer, 1, 2);
+        assert_ne!(history.state(), saved);
+        history.undo(&mut text_buffer);
+        assert_eq!(history.state(), saved);
+        history.clear();
+        assert_eq!(history.state(), 0);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn applied_includes_undos() {
+        let mut text_buffer = TextBuffer::new();
+        let mut history = History::new();
+        history.record_applied();
+        history.apply(insert(0, "a"), EditKind::Typing, &mut text_buffer, 0, 1);
+        history.undo(&mut text_buffer);
+        assert_eq!(
+            history.take_applied(),
+            [insert(0, "a"), remove(0, "a")].concat()
+        );
+        assert!(history.take_applied().is_empty());
+    }
+}
@@ -0,0 +1,452 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::command::{ControlCommand, KeyCommand, Modifiers, Motion, TextCommand};
+
+//the mac bindings, every preset starts from these
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("left", "move-left"),
+    ("right", "move-right"),
+    ("up", "move-up"),
+    ("down", "move-down"),
+    ("home", "move-line-start"),
+    ("end", "move-line-end"),
+    ("page-up", "move-page-up"),
+    ("page-down", "move-page-down"),
+    ("shift+left", "select-left"),
+    ("shift+right", "select-right"),
+    ("shift+up", "select-up"),
+    ("shift+down", "select-down"),
+    ("shift+home", "select-line-start"),
+    ("shift+end", "select-line-end"),
+    ("shift+page-up", "select-page-up"),
+    ("shift+page-down", "select-page-down"),
+    ("option+left", "move-word-left"),
+    ("option+right", "move-word-right"),
+    ("option+up", "move-paragraph-up"),
+    ("option+down", "move-paragraph-down"),
+    ("option+shift+left", "select-word-left"),
+    ("option+shift+right", "select-word-right"),
+    ("option+shift+up", "select-paragraph-up"),
+    ("option+shift+down", "select-paragraph-down"),
+    ("cmd+left", "move-line-start"),
+    ("cmd+right", "move-line-end"),
+    ("cmd+up", "move-doc-start"),
+    ("cmd+down", "move-doc-end"),
+    ("cmd+shift+left", "select-line-start"),
+    ("cmd+shift+right", "select-line-end"),
+    ("cmd+shift+up", "select-doc-start"),
+    ("cmd+shift+down", "select-doc-end"),
+    ("cmd+option+up", "add-cursor-up"),
+    ("cmd+option+down", "add-cursor-down"),
+    ("escape", "collapse-cursors"),
+    ("enter", "newline"),
+    ("shift+enter", "newline"),
+    ("backspace", "delete-backward"),
+    ("shift+backspace", "delete-backward"),
+    ("delete", "delete-forward"),
+    ("option+backspace", "delete-word-backward"),
+    ("option+delete", "delete-word-forward"),
+    ("cmd+a", "select-all"),
+    ("cmd+l", "select-line"),
+    ("cmd+d", "select-word"),
+    ("cmd+c", "copy"),
+    ("cmd+x", "cut"),
+    ("cmd+v", "paste"),
+    ("cmd+z", "undo"),
+    ("cmd+shift+z", "redo"),
+    ("cmd+s", "save"),
+];
+
+//emacs movement and killing on top of the mac bindings, option is meta
+const EMACS_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl+a", "move-paragraph-start"),
+    ("ctrl+e", "move-paragraph-end"),
+    ("ctrl+f", "move-right"),
+    ("ctrl+b", "move-left"),
+    ("ctrl+n", "move-down"),
+    ("ctrl+p", "move-up"),
+    ("option+f", "move-word-right"),
+    ("option+b", "move-word-left"),
+    ("ctrl+v", "move-page-down"),
+    ("option+v", "move-page-up"),
+    ("option+<", "move-doc-start"),
+    ("option+>", "move-doc-end"),
+    ("ctrl+d", "delete-forward"),
+    ("ctrl+k", "kill-line"),
+    ("option+d", "kill-word-right"),
+    ("option+backspace", "kill-word-left"),
+    ("ctrl+w", "kill-region"),
+    ("option+w", "copy-region"),
+    ("ctrl+y", "yank"),
+    ("option+y", "yank-pop"),
+    ("ctrl+space", "set-mark"),
+    ("ctrl+g", "cancel"),
+    ("ctrl+/", "undo"),
+];
+
+//bindings to start from, config bindings are applied on top
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Mac,
+    Emacs,
+}
+
+const MOTIONS: &[(&str, Motion)] = &[
+    ("left", Motion::Left),
+    ("right", Motion::Right),
+    ("up", Motion::Up),
+    ("down", Motion::Down),
+    ("line-start", Motion::LineStart),
+    ("line-end", Motion::LineEnd),
+    ("page-up", Motion::PageUp),
+    ("page-down", Motion::PageDown),
+    ("doc-start", Motion::DocStart),
+    ("doc-end", Motion::DocEnd),
+    ("word-left", Motion::WordLeft),
+    ("word-right", Motion::WordRight),
+    ("paragraph-up", Motion::ParagraphUp),
+    ("paragraph-down", Motion::ParagraphDown),
+    ("paragraph-start", Motion::ParagraphStart),
+    ("paragraph-end", Motion::ParagraphEnd),
+    ("left-in-line", Motion::LeftInLine),
+    ("right-in-line", Motion::RightInLine),
+    ("next-word-start", Motion::NextWordStart),
+    ("word-end", Motion::WordEnd),
+    ("next-word-start-in-line", Motion::NextWordStartInLine),
+];
+
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("page-up", Key::PageUp),
+    ("page-down", Key::PageDown),
+    ("backspace", Key::Backspace),
+    ("delete", Key::Delete),
+    ("enter", Key::Enter),
+    ("escape", Key::Escape),
+    ("tab", Key::Tab),
+    ("space", Key::Char(' ')),
+];
+
+//a physical key, the frontend translates its native key codes into these
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    Char(char),
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Backspace,
+    Delete, //forward delete
+    Enter,
+    Escape,
+    Tab,
+    F(u8),
+    //control chars and function keys without a name, never typed and can't be bound
+    Other(char),
+}
+
+impl From<char> for Key {
+    fn from(value: char) -> Self {
+        match value {
+            '\u{7f}' | '\u{8}' => Key::Backspace,
+            '\r' | '\n' => Key::Enter,
+            '\u{1b}' => Key::Escape,
+            '\t' => Key::Tab,
+            _ if value.is_control() => Key::Other(value),
+            _ => Key::Char(value),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, key)) = NAMED_KEYS.iter().find(|(name, _)| *name == s) {
+            return Ok(*key);
+        }
+        if let Some(number) = s.strip_prefix('f').and_then(|n| n.parse::<u8>().ok())
+            && (1..=35).contains(&number)
+        {
+            return Ok(Key::F(number));
+        }
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(char), None) => Ok(Key::Char(char.to_ascii_lowercase())),
+            _ => Err(KeymapError::UnknownKey(s.to_string())),
+        }
+    }
+}
+
+//modifiers plus a key, written like "cmd+shift+z" in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyChord {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl KeyChord {
+    //shift+letter arrives uppercase, bindings are written with the lowercase letter
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        let key = match key {
+            Key::Char(char) => Key::Char(char.to_ascii_lowercase()),
+            key => key,
+        };
+        Self { key, modifiers }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //the key itself can be "+", as in "cmd++"
+        let (modifier_names, key_name) = match s.strip_suffix('+') {
+            Some(rest) => (rest.strip_suffix('+').unwrap_or(rest), "+"),
+            None => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+        let mut modifiers = Modifiers::default();
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            let modifier = match name {
+                "shift" => &mut modifiers.shift,
+                "ctrl" | "control" => &mut modifiers.control,
+                "option" | "alt" => &mut modifiers.option,
+                "cmd" | "command" => &mut modifiers.command,
+                _ => return Err(KeymapError::UnknownModifier(name.to_string())),
+            };
+            *modifier = true;
+        }
+        Ok(KeyChord::new(key_name.parse()?, modifiers))
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = KeymapError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+//a command name from the config, "none" unbinds the chord
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Binding(pub Option<KeyCommand>);
+
+impl FromStr for Binding {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(Binding(None));
+        }
+        let motion = |name: &str| {
+            MOTIONS
+                .iter()
+                .find(|(motion_name, _)| *motion_name == name)
+                .map(|(_, motion)| *motion)
+        };
+        let control_command = match s {
+            "select-word" => Some(ControlCommand::SelectWord),
+            "select-line" => Some(ControlCommand::SelectLine),
+            "select-all" => Some(ControlCommand::SelectAll),
+            "delete-forward" => Some(ControlCommand::DeleteForward),
+            "delete-word-backward" => Some(ControlCommand::DeleteWordBackward),
+            "delete-word-forward" => Some(ControlCommand::DeleteWordForward),
+            "add-cursor-up" => Some(ControlCommand::AddCursor(Motion::Up)),
+            "add-cursor-down" => Some(ControlCommand::AddCursor(Motion::Down)),
+            "collapse-cursors" => Some(ControlCommand::CollapseCursors),
+            "copy" => Some(ControlCommand::Copy),
+            "cut" => Some(ControlCommand::Cut),
+            "paste" => Some(ControlCommand::Paste),
+            "undo" => Some(ControlCommand::Undo),
+            "redo" => Some(ControlCommand::Redo),
+            "save" => Some(ControlCommand::Save),
+            "set-mark" => Some(ControlCommand::SetMark),
+            "cancel" => Some(ControlCommand::Cancel),
+            "kill-line" => Some(ControlCommand::KillLine),
+            "kill-region" => Some(ControlCommand::KillRegion),
+            "copy-region" => Some(ControlCommand::CopyRegion),
+            "yank" => Some(ControlCommand::Yank),
+            "yank-pop" => Some(ControlCommand::YankPop),
+            _ => None,
+        }
+        .or_else(|| {
+            s.strip_prefix("move-")
+                .and_then(motion)
+                .map(ControlCommand::Move)
+        })
+        .or_else(|| {
+            s.strip_prefix("select-")
+                .and_then(motion)
+                .map(ControlCommand::Select)
+        })
+        .or_else(|| {
+            s.strip_prefix("kill-")
+                .and_then(motion)
+                .map(ControlCommand::Kill)
+        });
+        let command = match (s, control_command) {
+            (_, Some(control_command)) => KeyCommand::CC(control_command),
+            ("newline", None) => KeyCommand::TC(TextCommand::Newline),
+            ("delete-backward", None) => KeyCommand::TC(TextCommand::Backspace),
+            _ => return Err(KeymapError::UnknownCommand(s.to_string())),
+        };
+        Ok(Binding(Some(command)))
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = KeymapError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    UnknownKey(String),
+    UnknownModifier(String),
+    UnknownCommand(String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::UnknownKey(key) => write!(f, "unknown key `{key}`"),
+            KeymapError::UnknownModifier(modifier) => write!(f, "unknown modifier `{modifier}`"),
+            KeymapError::UnknownCommand(command) => write!(f, "unknown command `{command}`"),
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+//key chords to editor commands, keys with no binding type their char if they have one
+//and are ignored otherwise
+pub struct Keymap {
+    bindings: HashMap<KeyChord, KeyCommand>,
+    //option chords are meta commands rather than typed symbols, unbound ones do nothing
+    option_is_meta: bool,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            option_is_meta: false,
+        }
+    }
+
+    pub fn bind(&mut self, chord: KeyChord, binding: Binding) {
+        match binding.0 {
+            Some(command) => self.bindings.insert(chord, command),
+            None => self.bindings.remove(&chord),
+        };
+    }
+
+    pub fn preset(preset: Preset) -> Self {
+        let tables = match preset {
+            Preset::Mac => vec![DEFAULT_BINDINGS],
+            Preset::Emacs => vec![DEFAULT_BINDINGS, EMACS_BINDINGS],
+        };
+        let mut keymap = Keymap::new();
+        keymap.option_is_meta = preset == Preset::Emacs;
+        for (chord, binding) in tables.into_iter().flatten() {
+            let chord = chord.parse().expect("preset chords are valid");
+            let binding = binding.parse().expect("preset commands are valid");
+            keymap.bind(chord, binding);
+        }
+        keymap
+    }
+
+    pub fn command_for(&self, key: Key, modifiers: Modifiers) -> KeyCommand {
+        if let Some(command) = self.bindings.get(&KeyChord::new(key, modifiers)) {
+            return *command;
+        }
+        //shift is already part of symbols like < so "option+<" matches without it
+        if let Key::Char(char) = key
+            && modifiers.shift
+            && !char.is_alphabetic()
+        {
+            let unshifted = Modifiers {
+                shift: false,
+                ..modifiers
+            };
+            if let Some(command) = self.bindings.get(&KeyChord::new(key, unshifted)) {
+                return *command;
+            }
+        }
+        let meta = modifiers.option && self.option_is_meta;
+        match key {
+            //command and control chords never type anything, nor do meta ones
+            Key::Char(char) if !modifiers.command && !modifiers.control && !meta => {
+                KeyCommand::TC(TextCommand::Char(char))
+            }
+            _ => KeyCommand::Unknown,
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset(Preset::Mac)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTION: Modifiers = Modifiers {
+        shift: false,
+        control: false,
+        option: true,
+        command: false,
+    };
+
+    #[test]
+    fn bound_meta_chords_run_their_command() {
+        let keymap = Keymap::preset(Preset::Emacs);
+        assert_eq!(
+            keymap.command_for(Key::Char('f'), OPTION),
+            KeyCommand::CC(ControlCommand::Move(Motion::WordRight))
+        );
+    }
+
+    #[test]
+    fn unbound_option_chords_only_type_outside_emacs() {
+        let emacs = Keymap::preset(Preset::Emacs);
+        assert_eq!(
+            emacs.command_for(Key::Char('z'), OPTION),
+            KeyCommand::Unknown
+        );
+        let mac = Keymap::preset(Preset::Mac);
+        assert_eq!(
+            mac.command_for(Key::Char('Ω'), OPTION),
+            KeyCommand::TC(TextCommand::Char('Ω'))
+        );
+    }
+
+    #[test]
+    fn control_chars_are_not_typed() {
+        assert_eq!(Key::from('\u{1b}'), Key::Escape);
+        assert_eq!(Key::from('\u{1}'), Key::Other('\u{1}'));
+        assert_eq!(TextCommand::from('\u{1}'), TextCommand::Unknown);
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+const KILL_RING_CAPACITY: usize = 60;
+
+//emacs style kill ring, newest entry first
+pub struct KillRing {
+    entries: VecDeque<String>,
+    //a kill right after another kill adds to the same entry
+    appending: bool,
+    //entry the last yank or yank-pop inserted, yank-pop only works right after one of them
+    yanked: Option<usize>,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            appending: false,
+            yanked: None,
+        }
+    }
+
+    //backward kills go in front of what was killed before them
+    pub fn kill(&mut self, text: &str, backward: bool) {
+        match self.entries.front_mut() {
+            Some(entry) if self.appending => match backward {
+                true => entry.insert_str(0, text),
+                false => entry.push_str(text),
+            },
+            _ => self.push(text.to_string()),
+        }
+        self.appending = true;
+        self.yanked = None;
+    }
+
+    //text copied somewhere else becomes the newest entry, unless it's already there
+    pub fn push(&mut self, text: String) {
+        if self.entries.front() == Some(&text) {
+            return;
+        }
+        self.entries.push_front(text);
+        self.entries.truncate(KILL_RING_CAPACITY);
+    }
+
+    pub fn latest(&self) -> Option<&str> {
+        self.entries.front().map(String::as_str)
+    }
+
+    pub fn yank(&mut self) -> Option<&str> {
+        self.appending = false;
+        self.yanked = (!self.entries.is_empty()).then_some(0);
+        self.latest()
+    }
+
+    //the entry before the last yanked one, wrapping around
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        let next = (self.yanked? + 1) % self.entries.len();
+        self.yanked = Some(next);
+        Some(&self.entries[next])
+    }
+
+    //anything that isn't a kill or a yank ends the current run of them
+    pub fn seal(&mut self) {
+        self.appending = false;
+        self.yanked = None;
+    }
+}
+
+impl Default for KillRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kills_append_until_sealed() {
+        let mut ring = KillRing::new();
+        ring.kill("b", false);
+        ring.kill("c", false);
+        ring.kill("a", true);
+        assert_eq!(ring.latest(), Some("abc"));
+        ring.seal();
+        ring.kill("d", false);
+        assert_eq!(ring.latest(), Some("d"));
+    }
+
+    #[test]
+    fn yank_pop_rotates_back_to_the_start() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank_pop(), None);
+        for text in ["one", "two", "three"] {
+            ring.kill(text, false);
+            ring.seal();
+        }
+        assert_eq!(ring.yank(), Some("three"));
+        assert_eq!(ring.yank_pop(), Some("two"));
+        assert_eq!(ring.yank_pop(), Some("one"));
+        assert_eq!(ring.yank_pop(), Some("three"));
+        ring.seal();
+        assert_eq!(ring.yank_pop(), None);
+    }
+
+    #[test]
+    fn pushing_the_newest_entry_again_keeps_one_copy() {
+        let mut ring = KillRing::new();
+        ring.push("copied".to_string());
+        ring.push("copied".to_string());
+        ring.yank();
+        assert_eq!(ring.yank_pop(), Some("copied"));
+    }
+}
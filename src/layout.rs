@@ -0,0 +1,541 @@
+use std::ops::Range;
+
+use freetype::ffi::FT_Vector;
+
+use crate::{
+    atlas::{Atlas, GlyphKey},
+    buffer::TextBuffer,
+    command::TextCommand,
+    maths::{Float2, Float4, apply_rotation_float2, float2_add, float2_subtract},
+    text::{FontChain, shaping::shape_text},
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct vertex_t {
+    pub position: Float4,
+    pub uv: Float4,
+    pub color: Float4,
+}
+
+//bottom left corner rect
+pub fn build_rect(
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    rot: f32,
+    color: Float4,
+) -> Vec<vertex_t> {
+    let mut verts = Vec::new();
+
+    let origin = Float2(x, y - height);
+    let v1_pos = origin;
+    let v1_rot_pos = float2_add(
+        apply_rotation_float2(float2_subtract(v1_pos, origin), rot),
+        origin,
+    );
+    let vert1 = vertex_t {
+        position: Float4(v1_rot_pos.0, v1_rot_pos.1, 0.0, 1.0),
+        uv: Float4(0.0, height, 0.0, 0.0),
+        color,
+    };
+
+    let v2_pos = Float2(x + width, y - height);
+    let v2_rot_pos = float2_add(
+        apply_rotation_float2(float2_subtract(v2_pos, origin), rot),
+        origin,
+    );
+    let vert2 = vertex_t {
+        position: Float4(v2_rot_pos.0, v2_rot_pos.1, 0.0, 1.0),
+        uv: Float4(width, height, 0.0, 0.0),
+        color,
+    };
+
+    let v3_pos = Float2(x, y);
+    let v3_rot_pos = float2_add(
+        apply_rotation_float2(float2_subtract(v3_pos, origin), rot),
+        origin,
+    );
+    let vert3 = vertex_t {
+        position: Float4(v3_rot_pos.0, v3_rot_pos.1, 0.0, 1.0),
+        uv: Float4(0.0, 0.0, 0.0, 0.0),
+        color,
+    };
+
+    let v4_pos = Float2(x + width, y);
+    let v4_rot_pos = float2_add(
+        apply_rotation_float2(float2_subtract(v4_pos, origin), rot),
+        origin,
+    );
+    let vert4 = vertex_t {
+        position: Float4(v4_rot_pos.0, v4_rot_pos.1, 0.0, 1.0),
+        uv: Float4(width, 0.0, 0.0, 0.0),
+        color,
+    };
+
+    verts.push(vert1);
+    verts.push(vert2);
+    verts.push(vert3);
+    verts.push(vert2);
+    verts.push(vert3);
+    verts.push(vert4);
+
+    verts
+}
+pub fn build_cursor_verts(
+    cursor: Float2,
+    index: usize,
+    char_positions: &[Float2],
+    font_size: u32,
+    counter: &mut u32,
+) -> Vec<vertex_t> {
+    let counter_const = 50;
+    *counter = (*counter + 1) % counter_const;
+    let current_cursor_color = if *counter <= counter_const / 2 {
+        Float4(0.0, 0.0, 0.0, 1.0)
+    } else {
+        Float4(1.0, 1.0, 1.0, 0.0)
+    };
+    // let location = char_positions[index.checked_sub(1).unwrap_or(0)];
+    let location = match index == char_positions.len() {
+        true => cursor,
+        false => char_positions[index],
+    };
+    build_rect(
+        location.0,
+        location.1,
+        font_size as f32 / 20.0,
+        font_size as f32,
+        0.0,
+        current_cursor_color,
+    )
+}
+
+//one rect per visual line the range covers, a line's last char has no next position to
+//measure against so it gets a fixed width, which also shows selected newlines
+pub fn build_selection_verts(
+    range: Range<usize>,
+    char_positions: &[Float2],
+    line_height: f32,
+    color: Float4,
+) -> Vec<vertex_t> {
+    let mut verts = Vec::new();
+    let end = range.end.min(char_positions.len());
+    let mut line_start = range.start;
+    for index in range.start..end {
+        let position = char_positions[index];
+        let next = char_positions
+            .get(index + 1)
+            .filter(|next| next.1 == position.1);
+        if next.is_some() && index + 1 < end {
+            continue;
+        }
+        let right = match next {
+            Some(next) => next.0,
+            None => position.0 + line_height * 0.3,
+        };
+        let left = char_positions[line_start];
+        verts.append(&mut build_rect(
+            left.0,
+            left.1,
+            right - left.0,
+            line_height,
+            0.0,
+            color,
+        ));
+        line_start = index + 1;
+    }
+    verts
+}
+
+//visual lines as char ranges, wrapped lines count as their own line
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len_chars: usize,
+}
+
+impl LineIndex {
+    pub fn new(starts: Vec<usize>, len_chars: usize) -> Self {
+        Self { starts, len_chars }
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    pub fn line_of(&self, char_idx: usize) -> usize {
+        self.starts
+            .partition_point(|&start| start <= char_idx)
+            .saturating_sub(1)
+    }
+
+    //includes the line's trailing newline or wrapping whitespace
+    pub fn line_range(&self, line: usize) -> Range<usize> {
+        let end = self.starts.get(line + 1).copied().unwrap_or(self.len_chars);
+        self.starts[line]..end
+    }
+}
+
+//contiguous run of glyph instances that sample from the same atlas page
+#[derive(Debug, Clone, Copy)]
+pub struct PageRange {
+    pub page: usize,
+    pub start: usize,
+    pub count: usize,
+}
+
+//glyph placed relative to the first char of its cluster, so it follows that char when wrapping
+struct PlacedGlyph {
+    key: GlyphKey,
+    char_index: usize,
+    offset: Float2,
+}
+
+//positions built up run by run, chars that aren't shaped are pushed directly
+struct TextLayout<'a> {
+    cursor: &'a mut Float2,
+    initial_cursor_pos: Float2,
+    text_box_width: f32,
+    text_buffer: &'a TextBuffer,
+    //char index of char_positions[0], everything else here is indexed into the whole text
+    start: usize,
+    char_positions: Vec<Float2>,
+    placed_glyphs: Vec<PlacedGlyph>,
+    //first char of every visual line, wrapped ones included
+    line_starts: Vec<usize>,
+}
+
+impl TextLayout<'_> {
+    //shapes a run of printable chars that all resolved to the same font in the chain
+    fn layout_run(&mut self, run: &str, font_index: usize, fonts: &FontChain, atlas: &mut Atlas) {
+        let font = fonts.get(font_index);
+        let cursor = &mut *self.cursor;
+        let start = self.start;
+        let char_positions = &mut self.char_positions;
+        let run_start = start + char_positions.len();
+        let run_len = run.chars().count();
+        let mut shaped = shape_text(font, run);
+        //logical order, only matters for rtl runs which come back reversed
+        shaped.sort_by_key(|glyph| glyph.cluster);
+
+        let mut glyph_index = 0;
+        while glyph_index < shaped.len() {
+            let cluster = shaped[glyph_index].cluster;
+            let group_len = shaped[glyph_index..]
+                .iter()
+                .take_while(|glyph| glyph.cluster == cluster)
+                .count();
+            let group = &shaped[glyph_index..glyph_index + group_len];
+            glyph_index += group_len;
+            let cluster_start = if run_start == start + char_positions.len() {
+                0
+            } else {
+                cluster
+            };
+            let cluster_end = shaped
+                .get(glyph_index)
+                .map_or(run_len, |glyph| glyph.cluster);
+
+            //chars in a ligature split its advance evenly so the cursor can sit inside it
+            let cluster_advance: f32 = group.iter().map(|glyph| glyph.advance.0).sum();
+            let cluster_chars = cluster_end - cluster_start;
+            for k in 0..cluster_chars {
+                let fraction = k as f32 / cluster_chars as f32;
+                char_positions.push(*cursor + Float2(cluster_advance * fraction, 0.0));
+            }
+
+            let first_char = run_start + cluster_start;
+            let last_char = run_start + cluster_end - 1;
+            let mut pen = 0.0;
+            for glyph in group {
+                //glyphs missing from the atlas get rasterized and packed here, before any
+                //verts are built, and everything touched this layout is pinned against eviction
+                let key = GlyphKey {
+                    font: font_index,
+                    glyph_id: glyph.glyph_id,
+                };
+                if atlas.glyph(&font.face, key).is_ok() {
+                    self.placed_glyphs.push(PlacedGlyph {
+                        key,
+                        char_index: first_char,
+                        offset: Float2(pen + glyph.offset.0, glyph.offset.1),
+                    });
+                }
+                pen += glyph.advance.0;
+            }
+            cursor.0 += cluster_advance;
+
+            if cursor.0 - self.initial_cursor_pos.0 >= self.text_box_width {
+                //only break inside the current visual line, not at whitespace from one above it
+                let line_start = self.line_starts.last().copied().unwrap_or_default();
+                let index = self
+                    .text_buffer
+                    .rfind_in(line_start..first_char, char::is_whitespace)
+                    .map(|ws_index| ws_index + 1)
+                    .filter(|&index| index > line_start)
+                    .unwrap_or(first_char);
+
+                let cursor_diff = char_positions[index - start].0 - self.initial_cursor_pos.0;
+                if index > line_start {
+                    self.line_starts.push(index);
+                }
+                if cursor_diff <= 0.0 {
+                    let cluster_diff =
+                        char_positions[first_char - start].0 - self.initial_cursor_pos.0;
+                    for position in &mut char_positions[first_char - start..=last_char - start] {
+                        position.0 -= cluster_diff;
+                        position.1 -= atlas.line_height;
+                    }
+                } else {
+                    let height_diff = atlas.line_height;
+                    for position in &mut char_positions[index - start..=last_char - start] {
+                        position.0 -= cursor_diff;
+                        position.1 -= height_diff;
+                    }
+                }
+                cursor.0 = char_positions[first_char - start].0 + cluster_advance;
+                cursor.1 -= atlas.line_height * 1.05;
+            }
+        }
+    }
+}
+
+//Realized that I'm recalculating text wrapping every character draw
+//might not be necessary for simple rendering system I currently have
+//runs of printable chars are itemized by font and shaped as a unit,
+//so kerning and ligatures come from the font
+//glyph instances come back grouped by atlas page, char_positions stay in text order
+//line_starts is the first char index of every visual line
+//only the chars in window are laid out, starting at the cursor, char_positions[0] is the
+//window's first char
+#[allow(clippy::type_complexity)]
+pub fn verts_from_text(
+    cursor: &mut Float2,
+    text_buffer: &TextBuffer,
+    window: Range<usize>,
+    text_box_width: f32,
+    color: Float4,
+    atlas: &mut Atlas,
+    fonts: &FontChain,
+) -> (
+    Vec<vertex_t>,
+    Vec<Float2>,
+    Vec<Float2>,
+    Vec<PageRange>,
+    Vec<usize>,
+) {
+    let mut all_verts = Vec::new();
+    let mut all_tex_pointers = Vec::new();
+    let mut page_ranges: Vec<PageRange> = Vec::new();
+
+    atlas.begin_layout();
+    let initial_cursor_pos = *cursor;
+    let mut layout = TextLayout {
+        cursor,
+        initial_cursor_pos,
+        text_box_width,
+        text_buffer,
+        start: window.start,
+        char_positions: Vec::new(),
+        placed_glyphs: Vec::new(),
+        line_starts: vec![window.start],
+    };
+    let mut run = String::new();
+    let mut run_font = 0;
+    //itemized by grapheme cluster so a cluster is never split across fonts or shaping runs,
+    //trailing None flushes the last run
+    for grapheme in text_buffer
+        .graphemes(window.clone())
+        .map(Some)
+        .chain(std::iter::once(None))
+    {
+        let grapheme = grapheme.map(|(_, grapheme)| grapheme);
+        let command = grapheme
+            .and_then(|grapheme| grapheme.chars().next())
+            .map(TextCommand::from);
+        let grapheme_font = match command {
+            Some(TextCommand::Char(char)) => Some(fonts.font_for_char(char)),
+            _ => None,
+        };
+        if let (Some(grapheme), Some(font_index)) = (grapheme, grapheme_font)
+            && (run.is_empty() || font_index == run_font)
+        {
+            run_font = font_index;
+            run.extend(grapheme.chars());
+            continue;
+        }
+
+        if !run.is_empty() {
+            layout.layout_run(&run, run_font, fonts, atlas);
+            run.clear();
+        }
+
+        let Some(grapheme) = grapheme else {
+            continue;
+        };
+        match command {
+            //font changed, this grapheme starts the next run
+            Some(TextCommand::Char(_)) => {
+                run_font = grapheme_font.unwrap_or_default();
+                run.extend(grapheme.chars());
+            }
+            Some(TextCommand::Newline) => {
+                layout.char_positions.push(*layout.cursor);
+                layout
+                    .line_starts
+                    .push(window.start + layout.char_positions.len());
+                newline(initial_cursor_pos, layout.cursor, atlas.line_height);
+            }
+            //zero width, but still gets a position so indices stay in line with the buffer
+            _ => {
+                for _ in grapheme.chars() {
+                    layout.char_positions.push(*layout.cursor);
+                }
+            }
+        }
+    }
+    let TextLayout {
+        char_positions,
+        placed_glyphs,
+        mut line_starts,
+        ..
+    } = layout;
+    //text ending in a newline has no chars on its last line
+    if line_starts.last() == Some(&(window.start + char_positions.len())) && line_starts.len() > 1 {
+        line_starts.pop();
+    }
+
+    //stable sort so each page can be drawn as one instanced call
+    let glyphs: Vec<_> = placed_glyphs
+        .iter()
+        .filter_map(|placed| Some((placed, atlas.get_glyph(placed.key)?)))
+        .collect();
+    let mut draw_order: Vec<usize> = (0..glyphs.len()).collect();
+    draw_order.sort_by_key(|&i| glyphs[i].1.page);
+
+    for i in draw_order {
+        let (placed, glyph) = glyphs[i];
+        let width = glyph.data.width as f32;
+        let height = glyph.data.height as f32;
+        let origin = char_positions[placed.char_index - window.start] + placed.offset;
+        let baseline = origin.1 - atlas.line_height;
+        all_verts.append(&mut build_rect(
+            origin.0 + glyph.data.cbox.xMin as f32,
+            baseline + glyph.data.cbox.yMin as f32 + height,
+            width,
+            height,
+            0.0,
+            color,
+        ));
+        let (tex_x, tex_y) = glyph.tex_pointer();
+        all_tex_pointers.push(Float2(tex_x as f32, tex_y as f32));
+
+        match page_ranges.last_mut() {
+            Some(range) if range.page == glyph.page => range.count += 1,
+            _ => page_ranges.push(PageRange {
+                page: glyph.page,
+                start: all_tex_pointers.len() - 1,
+                count: 1,
+            }),
+        }
+    }
+    (
+        all_verts,
+        all_tex_pointers,
+        char_positions,
+        page_ranges,
+        line_starts,
+    )
+}
+
+fn newline(initial_cursor_pos: Float2, cursor: &mut Float2, line_height: f32) {
+    cursor.0 = initial_cursor_pos.0;
+    cursor.1 -= line_height;
+}
+
+impl From<FT_Vector> for Float2 {
+    fn from(value: FT_Vector) -> Self {
+        Float2(value.x as f32, value.y as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use freetype::Library;
+
+    use super::*;
+    use crate::text::{Font, freetype::load_font_data, loader::EMBEDDED_FONT};
+
+    //public domain, it has no hebrew so those chars have to come from arial
+    const TUFFY: &[u8] = include_bytes!("text/testdata/Tuffy.ttf");
+
+    fn font(lib: &Library, data: &[u8]) -> Font {
+        load_font_data(lib, Rc::new(data.to_vec()), 0, 50).unwrap()
+    }
+
+    //x of every char and how many glyphs got drawn
+    fn layout_with(text: &str, fonts: &FontChain, atlas: &mut Atlas) -> (Vec<f32>, usize) {
+        let text_buffer = TextBuffer::from(text);
+        let (_, tex_pointers, char_positions, _, _) = verts_from_text(
+            &mut Float2(0.0, 0.0),
+            &text_buffer,
+            0..text_buffer.len_chars(),
+            1000.0,
+            Float4(0.0, 0.0, 0.0, 1.0),
+            atlas,
+            fonts,
+        );
+        let xs = char_positions.iter().map(|position| position.0).collect();
+        (xs, tex_pointers.len())
+    }
+
+    fn layout(text: &str) -> (Vec<f32>, usize) {
+        let lib = Library::init().unwrap();
+        let font = font(&lib, EMBEDDED_FONT);
+        let mut atlas = Atlas::new(&font.face).unwrap();
+        layout_with(text, &FontChain::new(font), &mut atlas)
+    }
+
+    #[test]
+    fn kerning_pairs_are_closer_together() {
+        let (kerned, _) = layout("AV");
+        let (plain, _) = layout("AA");
+        assert!(kerned[1] < plain[1]);
+    }
+
+    #[test]
+    fn a_cluster_shaped_into_one_glyph_splits_its_advance() {
+        //e + combining acute composes to é
+        let (xs, glyphs) = layout("e\u{301}x");
+        assert_eq!(glyphs, 2);
+        assert_eq!(xs.len(), 3);
+        assert_eq!(xs[1], xs[2] / 2.0);
+    }
+
+    #[test]
+    fn chars_missing_from_the_primary_font_are_drawn_from_a_fallback() {
+        let lib = Library::init().unwrap();
+        let mut fonts = FontChain::new(font(&lib, TUFFY));
+        fonts.push(font(&lib, EMBEDDED_FONT));
+        let mut atlas = Atlas::new(&fonts.primary().face).unwrap();
+        assert_eq!(fonts.font_for_char('a'), 0);
+        assert_eq!(fonts.font_for_char('\u{5d0}'), 1);
+        //nothing has it, the primary draws .notdef
+        assert_eq!(fonts.font_for_char('\u{e000}'), 0);
+
+        let (_, glyphs) = layout_with("a\u{5d0}", &fonts, &mut atlas);
+        assert_eq!(glyphs, 2);
+        let glyph_id = fonts.get(1).face.get_char_index(0x5d0).unwrap();
+        assert_ne!(glyph_id, 0);
+        let glyph = atlas.get_glyph(GlyphKey { font: 1, glyph_id }).unwrap();
+        assert!(glyph.data.width > 0);
+    }
+}
@@ -0,0 +1,21 @@
+pub mod atlas;
+pub mod buffer;
+pub mod cli;
+pub mod clipboard;
+pub mod command;
+pub mod config;
+pub mod document;
+pub mod editor;
+pub mod file_format;
+pub mod history;
+pub mod keymap;
+pub mod kill_ring;
+pub mod layout;
+pub mod maths;
+pub mod merge;
+pub mod packer;
+pub mod recovery;
+pub mod render;
+pub mod selection;
+pub mod text;
+pub mod vim;
@@ -58,7 +58,7 @@ impl Index<usize> for Float3 {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Float2(pub c_float, pub c_float);
 impl Float2 {
     pub fn magnitude(&self) -> f32 {
@@ -68,9 +68,6 @@ impl Float2 {
     pub fn normalized(&self) -> Float2 {
         Float2(self.0 / self.magnitude(), self.1 / self.magnitude())
     }
-    pub fn default() -> Float2 {
-        Float2(0.0, 0.0)
-    }
 }
 
 impl Add for Float2 {
@@ -0,0 +1,229 @@
+//line based three way merge, for when the file changes on disk while it has unsaved changes
+//lines end in \r like in the buffer
+
+pub const CONFLICT_MINE: &str = "<<<<<<< mine\r";
+pub const CONFLICT_SEPARATOR: &str = "=======\r";
+pub const CONFLICT_THEIRS: &str = ">>>>>>> on disk\r";
+//past this many differing lines the sides are treated as entirely changed, which keeps the
+//diff's memory bounded
+const MAX_EDITS: isize = 2000;
+
+//the merged text and how many conflicts in it got marked, taking both sides
+pub fn merge3(base: &str, mine: &str, theirs: &str) -> (String, usize) {
+    let base: Vec<&str> = base.split_inclusive('\r').collect();
+    let mine: Vec<&str> = mine.split_inclusive('\r').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\r').collect();
+    //for each base line, the line it matches on either side
+    let mine_matches = matches(&base, &mine);
+    let theirs_matches = matches(&base, &theirs);
+
+    let mut merged = String::new();
+    let mut conflicts = 0;
+    let (mut b, mut m, mut t) = (0, 0, 0);
+    loop {
+        //lines unchanged on both sides go straight through
+        if b < base.len() && mine_matches[b] == Some(m) && theirs_matches[b] == Some(t) {
+            merged.push_str(base[b]);
+            b += 1;
+            m += 1;
+            t += 1;
+            continue;
+        }
+        //otherwise everything up to the next line both sides kept is a changed chunk
+        let next = (b..base.len())
+            .find(|&index| mine_matches[index].is_some() && theirs_matches[index].is_some());
+        let (base_end, mine_end, theirs_end) = match next {
+            Some(index) => (
+                index,
+                mine_matches[index].unwrap(),
+                theirs_matches[index].unwrap(),
+            ),
+            None => (base.len(), mine.len(), theirs.len()),
+        };
+        let base_chunk = &base[b..base_end];
+        let mine_chunk = &mine[m..mine_end];
+        let theirs_chunk = &theirs[t..theirs_end];
+        if mine_chunk == base_chunk || mine_chunk == theirs_chunk {
+            theirs_chunk.iter().for_each(|line| merged.push_str(line));
+        } else if theirs_chunk == base_chunk {
+            mine_chunk.iter().for_each(|line| merged.push_str(line));
+        } else {
+            conflicts += 1;
+            merged.push_str(CONFLICT_MINE);
+            push_lines(&mut merged, mine_chunk);
+            merged.push_str(CONFLICT_SEPARATOR);
+            push_lines(&mut merged, theirs_chunk);
+            merged.push_str(CONFLICT_THEIRS);
+        }
+        if next.is_none() {
+            break;
+        }
+        (b, m, t) = (base_end, mine_end, theirs_end);
+    }
+    (merged, conflicts)
+}
+
+//a chunk at the end of the text can stop without a newline, the marker after it can't share its line
+fn push_lines(merged: &mut String, lines: &[&str]) {
+    lines.iter().for_each(|line| merged.push_str(line));
+    if !lines.is_empty() && !merged.ends_with('\r') {
+        merged.push('\r');
+    }
+}
+
+//longest common subsequence of lines by myers' diff, as the matching index in b for each line
+//of a, common prefixes and suffixes are matched up front since edits are usually small
+fn matches(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut result = vec![None; a.len()];
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    for (index, matched) in result[..prefix].iter_mut().enumerate() {
+        *matched = Some(index);
+    }
+    for index in 0..suffix {
+        result[a.len() - 1 - index] = Some(b.len() - 1 - index);
+    }
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    for (a_index, b_index) in myers(a_mid, b_mid) {
+        result[prefix + a_index] = Some(prefix + b_index);
+    }
+    result
+}
+
+//matching (a, b) index pairs in order, none if the two are too different to be worth it
+fn myers(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m).min(MAX_EDITS);
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    //the part of v each round read from, k -d-1 to d+1, to walk the path back from the end
+    let mut trace = Vec::new();
+    let mut found = false;
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = match k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                true => v[index + 1],
+                false => v[index - 1] + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        return Vec::new();
+    }
+
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let previous_x = match d {
+            0 => 0,
+            _ => at(previous_k),
+        };
+        let previous_y = previous_x - previous_k;
+        //the diagonal run after the edit that got here
+        while x > previous_x.max(0) && y > previous_y.max(0) {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    pairs.reverse();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(prefix: &str, count: usize) -> String {
+        (0..count)
+            .map(|index| format!("{prefix}{index}\r"))
+            .collect()
+    }
+
+    #[test]
+    fn changes_on_one_side_are_taken() {
+        let base = "a\rb\rc\r";
+        assert_eq!(
+            merge3(base, base, "a\rB\rc\r"),
+            ("a\rB\rc\r".to_string(), 0)
+        );
+        assert_eq!(
+            merge3(base, "a\rb\rc\rd", base),
+            ("a\rb\rc\rd".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn changes_on_both_sides_merge() {
+        let base = "a\rb\rc\rd\r";
+        let (merged, conflicts) = merge3(base, "A\rb\rc\rd\r", "a\rb\rc\rD\r");
+        assert_eq!((merged.as_str(), conflicts), ("A\rb\rc\rD\r", 0));
+        //the same change made on both sides is made once
+        let (merged, conflicts) = merge3(base, "a\rB\rc\rd\r", "a\rB\rc\rd\r");
+        assert_eq!((merged.as_str(), conflicts), ("a\rB\rc\rd\r", 0));
+    }
+
+    #[test]
+    fn overlapping_changes_are_marked() {
+        let (merged, conflicts) = merge3("a\rb\rc\r", "a\rmine\rc\r", "a\rtheirs\rc\r");
+        assert_eq!(conflicts, 1);
+        let expected =
+            format!("a\r{CONFLICT_MINE}mine\r{CONFLICT_SEPARATOR}theirs\r{CONFLICT_THEIRS}c\r");
+        assert_eq!(merged, expected);
+        //a last line without a newline still gets the marker on a line of its own
+        let (merged, _) = merge3("a", "b", "c");
+        let expected = format!("{CONFLICT_MINE}b\r{CONFLICT_SEPARATOR}c\r{CONFLICT_THEIRS}");
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn too_many_differences_make_one_chunk() {
+        let base = lines("base", MAX_EDITS as usize);
+        let theirs = lines("theirs", MAX_EDITS as usize);
+        let base_lines: Vec<&str> = base.split_inclusive('\r').collect();
+        let theirs_lines: Vec<&str> = theirs.split_inclusive('\r').collect();
+        assert!(myers(&base_lines, &theirs_lines).is_empty());
+        assert_eq!(merge3(&base, &base, &theirs), (theirs.clone(), 0));
+
+        let mine = base.replacen("base7\r", "mine\r", 1);
+        let (merged, conflicts) = merge3(&base, &mine, &theirs);
+        assert_eq!(conflicts, 1);
+        let expected =
+            format!("{CONFLICT_MINE}{mine}{CONFLICT_SEPARATOR}{theirs}{CONFLICT_THEIRS}");
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn myers_matches_the_common_lines() {
+        let a = ["a\r", "b\r", "c\r", "d\r"];
+        let b = ["b\r", "x\r", "c\r", "d\r", "e\r"];
+        assert_eq!(myers(&a, &b), [(1, 0), (2, 2), (3, 3)]);
+    }
+}
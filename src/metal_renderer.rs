@@ -0,0 +1,170 @@
+use metal::{Buffer, Device, MTLPrimitiveType, MetalLayer, RenderPipelineState, Texture};
+use txt_rs::{
+    atlas::Atlas,
+    layout::{PageRange, vertex_t},
+    maths::Float2,
+    render::Renderer,
+};
+
+use crate::utils::{
+    copy_to_buf, get_library, init_render_with_bufs, make_buf, make_buf_with_capacity,
+    new_render_pass_descriptor, prepare_pipeline_state, sync_atlas_textures,
+};
+
+//draws are recorded and only encoded at present, once there's a drawable to encode into
+enum DrawCall {
+    Glyphs,
+    //range of verts in rect_verts
+    Rects(usize, usize),
+}
+
+pub struct MetalRenderer {
+    device: Device,
+    layer: MetalLayer,
+    command_queue: metal::CommandQueue,
+    text_pipeline: RenderPipelineState,
+    rect_pipeline: RenderPipelineState,
+    uni_buf: Buffer,
+    //buffers and how many elements they have room for, regrown when an upload doesn't fit
+    text_vert_buf: (Buffer, usize),
+    text_tex_buf: (Buffer, usize),
+    rect_vert_buf: (Buffer, usize),
+    atlas_textures: Vec<(Texture, u64)>,
+    page_ranges: Vec<PageRange>,
+    rect_verts: Vec<vertex_t>,
+    draw_calls: Vec<DrawCall>,
+}
+
+impl MetalRenderer {
+    pub fn new(device: Device, layer: MetalLayer, screen_size: Float2) -> Self {
+        let shaderlib = get_library(&device);
+        let text_pipeline =
+            prepare_pipeline_state(&device, "box_vertex", "text_fragment", &shaderlib);
+        let rect_pipeline =
+            prepare_pipeline_state(&device, "box_vertex", "cursor_fragment", &shaderlib);
+        let command_queue = device.new_command_queue();
+        let uni_buf = make_buf(&vec![Uniforms { screen_size }], &device);
+
+        //metal won't make empty buffers, so each starts with room for a few glyphs
+        let initial_glyphs = 1000;
+        let text_vert_buf = (
+            make_buf_with_capacity::<vertex_t>(&[], initial_glyphs * 6, &device),
+            initial_glyphs * 6,
+        );
+        let text_tex_buf = (
+            make_buf_with_capacity::<Float2>(&[], initial_glyphs, &device),
+            initial_glyphs,
+        );
+        let rect_vert_buf = (
+            make_buf_with_capacity::<vertex_t>(&[], 6 * 16, &device),
+            6 * 16,
+        );
+
+        Self {
+            device,
+            layer,
+            command_queue,
+            text_pipeline,
+            rect_pipeline,
+            uni_buf,
+            text_vert_buf,
+            text_tex_buf,
+            rect_vert_buf,
+            atlas_textures: Vec::new(),
+            page_ranges: Vec::new(),
+            rect_verts: Vec::new(),
+            draw_calls: Vec::new(),
+        }
+    }
+}
+
+impl Renderer for MetalRenderer {
+    fn upload_atlas(&mut self, atlas: &Atlas) {
+        sync_atlas_textures(atlas, &mut self.atlas_textures, &self.device);
+    }
+
+    fn upload_glyph_instances(
+        &mut self,
+        verts: &[vertex_t],
+        tex_pointers: &[Float2],
+        page_ranges: &[PageRange],
+    ) {
+        upload(&mut self.text_vert_buf, verts, &self.device);
+        upload(&mut self.text_tex_buf, tex_pointers, &self.device);
+        self.page_ranges = page_ranges.to_vec();
+    }
+
+    fn draw_glyphs(&mut self) {
+        self.draw_calls.push(DrawCall::Glyphs);
+    }
+
+    fn draw_rects(&mut self, verts: &[vertex_t]) {
+        if verts.is_empty() {
+            return;
+        }
+        self.draw_calls
+            .push(DrawCall::Rects(self.rect_verts.len(), verts.len()));
+        self.rect_verts.extend_from_slice(verts);
+    }
+
+    fn present(&mut self) {
+        upload(&mut self.rect_vert_buf, &self.rect_verts, &self.device);
+        let command_buffer = self.command_queue.new_command_buffer();
+
+        let drawable = self.layer.next_drawable().unwrap();
+        let texture = drawable.texture();
+        let render_descriptor = new_render_pass_descriptor(texture);
+
+        let encoder = init_render_with_bufs(
+            &[&self.uni_buf, &self.text_vert_buf.0, &self.text_tex_buf.0],
+            render_descriptor,
+            &self.text_pipeline,
+            command_buffer,
+        );
+        for draw_call in self.draw_calls.drain(..) {
+            match draw_call {
+                DrawCall::Glyphs => {
+                    encoder.set_render_pipeline_state(&self.text_pipeline);
+                    encoder.set_vertex_buffer(1, Some(&self.text_vert_buf.0), 0);
+                    for range in &self.page_ranges {
+                        let (page_texture, _) = &self.atlas_textures[range.page];
+                        encoder.set_fragment_texture(0, Some(page_texture));
+                        encoder.draw_primitives_instanced_base_instance(
+                            MTLPrimitiveType::Triangle,
+                            0,
+                            6,                  //six verts per char
+                            range.count as u64, //num of chars on this page
+                            range.start as u64,
+                        );
+                    }
+                }
+                DrawCall::Rects(start, count) => {
+                    encoder.set_render_pipeline_state(&self.rect_pipeline);
+                    encoder.set_vertex_buffer(1, Some(&self.rect_vert_buf.0), 0);
+                    encoder.draw_primitives(MTLPrimitiveType::Triangle, start as u64, count as u64);
+                }
+            }
+        }
+        encoder.end_encoding();
+
+        command_buffer.present_drawable(drawable);
+        command_buffer.commit();
+        self.rect_verts.clear();
+    }
+}
+
+//copies into the buffer, swapping it for a bigger one first if the data doesn't fit
+fn upload<T>(buffer: &mut (Buffer, usize), data: &[T], device: &Device) {
+    if data.len() > buffer.1 {
+        let capacity = data.len().max(buffer.1 * 2);
+        *buffer = (make_buf_with_capacity(data, capacity, device), capacity);
+    } else if !data.is_empty() {
+        copy_to_buf(data, &buffer.0);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Uniforms {
+    screen_size: Float2,
+}
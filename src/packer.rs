@@ -0,0 +1,235 @@
+//shelf packer for glyph atlas pages, purely cpu side so it doesn't care what texture backs it
+//rects are placed left to right on horizontal shelves, a new shelf is opened under the last one
+//when nothing fits, freed rects are kept per shelf and reused by anything narrow enough
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    shelf: usize,
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    next_x: u32,
+    free: Vec<(u32, u32)>, //(x, width) gaps left by deallocations
+}
+
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    padding: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    //padding is left right/below every rect so linear filtering doesn't bleed between glyphs
+    pub fn new(width: u32, height: u32, padding: u32) -> Self {
+        Self {
+            width,
+            height,
+            padding,
+            shelves: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<Allocation> {
+        let padded_width = width + self.padding;
+        let padded_height = height + self.padding;
+        if padded_width > self.width || padded_height > self.height {
+            return None;
+        }
+
+        //tightest existing shelf that can take the rect, either in a gap or at its end
+        let best_shelf = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, shelf)| shelf.height >= padded_height)
+            .filter(|(_, shelf)| {
+                shelf.free.iter().any(|&(_, gap)| gap >= padded_width)
+                    || shelf.next_x + padded_width <= self.width
+            })
+            .min_by_key(|(_, shelf)| shelf.height - padded_height)
+            .map(|(index, _)| index);
+
+        //only settle for a much taller shelf if there's no room to open a new one
+        let shelf_index = match best_shelf {
+            Some(index) if self.shelves[index].height <= padded_height * 3 / 2 => index,
+            best_shelf => match self.open_shelf(padded_height) {
+                Some(index) => index,
+                None => best_shelf?,
+            },
+        };
+
+        let shelf = &mut self.shelves[shelf_index];
+        let x = match shelf.free.iter().position(|&(_, gap)| gap >= padded_width) {
+            Some(gap_index) => {
+                let (gap_x, gap_width) = shelf.free[gap_index];
+                if gap_width == padded_width {
+                    shelf.free.remove(gap_index);
+                } else {
+                    shelf.free[gap_index] = (gap_x + padded_width, gap_width - padded_width);
+                }
+                gap_x
+            }
+            None => {
+                let x = shelf.next_x;
+                shelf.next_x += padded_width;
+                x
+            }
+        };
+
+        Some(Allocation {
+            x,
+            y: shelf.y,
+            width,
+            height,
+            shelf: shelf_index,
+        })
+    }
+
+    pub fn deallocate(&mut self, allocation: Allocation) {
+        let shelf = &mut self.shelves[allocation.shelf];
+        let (mut x, mut width) = (allocation.x, allocation.width + self.padding);
+        //merge with the gaps either side, otherwise churn leaves slivers too narrow for any glyph
+        while let Some(gap_index) = shelf
+            .free
+            .iter()
+            .position(|&(gap_x, gap_width)| gap_x + gap_width == x || x + width == gap_x)
+        {
+            let (gap_x, gap_width) = shelf.free.swap_remove(gap_index);
+            x = x.min(gap_x);
+            width += gap_width;
+        }
+        if x + width == shelf.next_x {
+            shelf.next_x = x;
+        } else {
+            shelf.free.push((x, width));
+        }
+
+        //drop empty shelves off the bottom so their height can be reused
+        while self
+            .shelves
+            .last()
+            .is_some_and(|shelf| shelf.next_x == 0 && shelf.free.is_empty())
+        {
+            self.shelves.pop();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.shelves.clear();
+    }
+
+    fn open_shelf(&mut self, height: u32) -> Option<usize> {
+        let y = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        if y + height > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            next_x: 0,
+            free: Vec::new(),
+        });
+        Some(self.shelves.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(allocation: Option<Allocation>) -> Option<(u32, u32)> {
+        allocation.map(|allocation| (allocation.x, allocation.y))
+    }
+
+    #[test]
+    fn fills_shelves_left_to_right_then_downwards() {
+        let mut packer = ShelfPacker::new(100, 30, 0);
+        assert_eq!(position(packer.allocate(40, 10)), Some((0, 0)));
+        assert_eq!(position(packer.allocate(40, 10)), Some((40, 0)));
+        assert_eq!(position(packer.allocate(40, 10)), Some((0, 10)));
+        assert_eq!(position(packer.allocate(10, 10)), Some((80, 0)));
+        //taller than any shelf and no room left for a new one
+        assert_eq!(packer.allocate(10, 20), None);
+        assert_eq!(packer.allocate(101, 1), None);
+    }
+
+    #[test]
+    fn short_rects_open_a_shelf_rather_than_waste_a_tall_one() {
+        let mut packer = ShelfPacker::new(100, 100, 0);
+        assert_eq!(position(packer.allocate(10, 20)), Some((0, 0)));
+        assert_eq!(position(packer.allocate(10, 10)), Some((0, 20)));
+        assert_eq!(position(packer.allocate(10, 18)), Some((10, 0)));
+    }
+
+    #[test]
+    fn padding_is_left_between_rects() {
+        let mut packer = ShelfPacker::new(64, 64, 1);
+        assert_eq!(position(packer.allocate(10, 10)), Some((0, 0)));
+        assert_eq!(position(packer.allocate(10, 10)), Some((11, 0)));
+        assert_eq!(packer.allocate(64, 10), None);
+    }
+
+    #[test]
+    fn deallocated_gaps_are_reused() {
+        let mut packer = ShelfPacker::new(100, 10, 0);
+        let _a = packer.allocate(30, 10).unwrap();
+        let b = packer.allocate(30, 10).unwrap();
+        let _c = packer.allocate(30, 10).unwrap();
+        assert_eq!(packer.allocate(30, 10), None);
+        packer.deallocate(b);
+        assert_eq!(position(packer.allocate(20, 10)), Some((30, 0)));
+        assert_eq!(position(packer.allocate(10, 10)), Some((50, 0)));
+        assert_eq!(packer.allocate(11, 10), None);
+    }
+
+    #[test]
+    fn freeing_the_end_of_a_shelf_gives_the_space_back() {
+        let mut packer = ShelfPacker::new(100, 20, 0);
+        let _a = packer.allocate(30, 10).unwrap();
+        let b = packer.allocate(30, 10).unwrap();
+        let c = packer.allocate(30, 10).unwrap();
+        packer.deallocate(b);
+        packer.deallocate(c);
+        assert_eq!(position(packer.allocate(70, 10)), Some((30, 0)));
+    }
+
+    #[test]
+    fn neighbouring_gaps_merge() {
+        let mut packer = ShelfPacker::new(100, 10, 1);
+        let a = packer.allocate(19, 9).unwrap();
+        let b = packer.allocate(29, 9).unwrap();
+        let c = packer.allocate(29, 9).unwrap();
+        let _d = packer.allocate(19, 9).unwrap();
+        packer.deallocate(b);
+        packer.deallocate(c);
+        assert_eq!(position(packer.allocate(59, 9)), Some((20, 0)));
+        packer.deallocate(a);
+        assert_eq!(position(packer.allocate(19, 9)), Some((0, 0)));
+        assert_eq!(packer.allocate(1, 9), None);
+    }
+
+    #[test]
+    fn empty_bottom_shelves_are_dropped() {
+        let mut packer = ShelfPacker::new(100, 20, 0);
+        let _a = packer.allocate(100, 10).unwrap();
+        let b = packer.allocate(100, 5).unwrap();
+        assert_eq!(packer.allocate(1, 10), None);
+        packer.deallocate(b);
+        assert_eq!(position(packer.allocate(1, 10)), Some((0, 10)));
+    }
+}
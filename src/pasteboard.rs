@@ -0,0 +1,32 @@
+use objc2::rc::Retained;
+use objc2_app_kit::{NSPasteboard, NSPasteboardTypeString};
+use objc2_foundation::NSString;
+use txt_rs::clipboard::Clipboard;
+
+//the system clipboard, plain text only
+pub struct Pasteboard {
+    pasteboard: Retained<NSPasteboard>,
+}
+
+impl Pasteboard {
+    pub fn general() -> Self {
+        Self {
+            pasteboard: unsafe { NSPasteboard::generalPasteboard() },
+        }
+    }
+}
+
+impl Clipboard for Pasteboard {
+    fn read_text(&mut self) -> Option<String> {
+        let text = unsafe { self.pasteboard.stringForType(NSPasteboardTypeString) }?;
+        Some(text.to_string())
+    }
+
+    fn write_text(&mut self, text: &str) {
+        unsafe {
+            self.pasteboard.clearContents();
+            self.pasteboard
+                .setString_forType(&NSString::from_str(text), NSPasteboardTypeString);
+        }
+    }
+}
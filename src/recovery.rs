@@ -0,0 +1,279 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, ErrorKind, Write},
+    path::{self, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{buffer::TextBuffer, document::write_atomic, editor::Editor, history::EditOp};
+
+//how often the snapshot is rewritten while there are edits journaled since the last one
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+//unsaved changes to one file, kept outside of it so a crash doesn't lose them: a snapshot of
+//the whole text, rewritten every so often, and a journal of the edits made since, appended to
+//as they happen, both only exist while there are unsaved changes
+pub struct Recovery {
+    snapshot_path: PathBuf,
+    journal_path: PathBuf,
+    //open once this session has written a snapshot
+    journal: Option<File>,
+    journaled: usize,
+    last_snapshot: Instant,
+}
+
+impl Recovery {
+    //where recovery files go unless TXT_RS_RECOVERY says otherwise
+    pub fn dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("TXT_RS_RECOVERY") {
+            return Some(PathBuf::from(dir));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/txt-rs/recovery"))
+    }
+
+    //files are named after a hash of the absolute path, so each file has its own
+    pub fn new(dir: &Path, path: &Path) -> Recovery {
+        let path = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let name = format!("{:016x}", fnv1a(path.as_os_str().as_encoded_bytes()));
+        Recovery {
+            snapshot_path: dir.join(format!("{name}.txt")),
+            journal_path: dir.join(format!("{name}.journal")),
+            journal: None,
+            journaled: 0,
+            last_snapshot: Instant::now(),
+        }
+    }
+
+    //text a session that didn't exit cleanly left behind, the snapshot with the journal replayed
+    //on top, up to the first edit that was cut short or doesn't fit
+    pub fn load(&self) -> io::Result<Option<String>> {
+        let snapshot = match fs::read_to_string(&self.snapshot_path) {
+            Ok(snapshot) => snapshot,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let journal = match fs::read(&self.journal_path) {
+            Ok(journal) => journal,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        //a journal left from an older snapshot doesn't apply to this one
+        let journal = journal
+            .strip_prefix(journal_base(snapshot.as_bytes()).as_bytes())
+            .unwrap_or_default();
+        let mut text_buffer = TextBuffer::from(snapshot.as_str());
+        for op in parse_journal(journal) {
+            let fits = match &op {
+                EditOp::Insert { index, .. } => *index <= text_buffer.len_chars(),
+                EditOp::Remove { index, text } => text_buffer
+                    .len_chars()
+                    .checked_sub(*index)
+                    .is_some_and(|rest| rest >= text.chars().count()),
+            };
+            if !fits {
+                break;
+            }
+            op.apply(&mut text_buffer);
+        }
+        Ok(Some(text_buffer.to_string()))
+    }
+
+    //edits the editor applied since the last call, the first ones after a clean state start
+    //a new snapshot instead
+    pub fn record(&mut self, edits: &[EditOp], editor: &Editor) -> io::Result<()> {
+        let Some(journal) = &mut self.journal else {
+            return self.snapshot(editor);
+        };
+        if edits.is_empty() {
+            return Ok(());
+        }
+        let mut records = Vec::new();
+        for op in edits {
+            let (sign, index, text) = match op {
+                EditOp::Insert { index, text } => ('+', index, text),
+                EditOp::Remove { index, text } => ('-', index, text),
+            };
+            write!(records, "{sign}{index} {}\n{text}\n", text.len())?;
+        }
+        //a single write so a crash can only cut off the end of the journal
+        journal.write_all(&records)?;
+        self.journaled += records.len();
+        Ok(())
+    }
+
+    //rewrites the snapshot if there's been a journaled edit and some time since the last one
+    pub fn autosave(&mut self, editor: &Editor) -> io::Result<()> {
+        if self.journaled > 0 && self.last_snapshot.elapsed() >= AUTOSAVE_INTERVAL {
+            //a failed snapshot waits for the next interval too rather than retrying right away
+            self.last_snapshot = Instant::now();
+            self.snapshot(editor)?;
+        }
+        Ok(())
+    }
+
+    //the whole text, and an empty journal to go with it that starts with the snapshot's hash
+    pub fn snapshot(&mut self, editor: &Editor) -> io::Result<()> {
+        if let Some(dir) = self.snapshot_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = Vec::new();
+        editor.write_to(&mut text)?;
+        self.journal = None;
+        write_atomic(&self.snapshot_path, |writer| writer.write_all(&text))?;
+        let mut journal = File::create(&self.journal_path)?;
+        journal.write_all(journal_base(&text).as_bytes())?;
+        self.journal = Some(journal);
+        self.journaled = 0;
+        self.last_snapshot = Instant::now();
+        Ok(())
+    }
+
+    //once the changes are saved or thrown away
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.journal = None;
+        self.journaled = 0;
+        remove_if_exists(&self.journal_path)?;
+        remove_if_exists(&self.snapshot_path)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.journal.is_some()
+    }
+}
+
+//first line of a journal, ties it to the snapshot it applies to
+fn journal_base(snapshot: &[u8]) -> String {
+    format!("base {:016x}\n", fnv1a(snapshot))
+}
+
+//records are a sign, the char index and the text's length in bytes on one line, then the text
+fn parse_journal(mut journal: &[u8]) -> Vec<EditOp> {
+    let mut ops = Vec::new();
+    while let Some(header_end) = journal.iter().position(|&byte| byte == b'\n') {
+        let Some((sign, index, len)) = std::str::from_utf8(&journal[..header_end])
+            .ok()
+            .and_then(parse_header)
+        else {
+            break;
+        };
+        let rest = &journal[header_end + 1..];
+        if rest.len() <= len || rest[len] != b'\n' {
+            break;
+        }
+        let Ok(text) = std::str::from_utf8(&rest[..len]) else {
+            break;
+        };
+        let text = text.to_string();
+        ops.push(match sign {
+            '+' => EditOp::Insert { index, text },
+            _ => EditOp::Remove { index, text },
+        });
+        journal = &rest[len + 1..];
+    }
+    ops
+}
+
+fn parse_header(header: &str) -> Option<(char, usize, usize)> {
+    let sign = header
+        .chars()
+        .next()
+        .filter(|sign| matches!(sign, '+' | '-'))?;
+    let (index, len) = header[1..].split_once(' ')?;
+    Some((sign, index.parse().ok()?, len.parse().ok()?))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+//stable across builds and platforms, unlike std's hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        document::tests::scratch_dir,
+        editor::tests::{BACKSPACE, NONE, editor, press},
+    };
+
+    //journals what's typed into an editor with a snapshot of the text it starts with
+    fn recording(dir: &Path, text: &str) -> (Recovery, Editor) {
+        let mut recovery = Recovery::new(dir, Path::new("/notes/todo.txt"));
+        let mut editor = editor(text);
+        editor.record_edits();
+        recovery.record(&editor.take_edits(), &editor).unwrap();
+        (recovery, editor)
+    }
+
+    fn type_keys(recovery: &mut Recovery, editor: &mut Editor, keys: &str) {
+        press(editor, keys, NONE);
+        recovery.record(&editor.take_edits(), editor).unwrap();
+    }
+
+    #[test]
+    fn the_journal_replays_on_top_of_the_snapshot() {
+        let dir = scratch_dir("recovery-round-trip");
+        let (mut recovery, mut editor) = recording(&dir, "to do");
+        type_keys(&mut recovery, &mut editor, "ne\r");
+        type_keys(&mut recovery, &mut editor, &BACKSPACE.to_string());
+        type_keys(&mut recovery, &mut editor, "!é");
+        assert_eq!(recovery.load().unwrap().as_deref(), Some("to done!é"));
+
+        recovery.clear().unwrap();
+        assert_eq!(recovery.load().unwrap(), None);
+        assert!(!recovery.is_active());
+    }
+
+    #[test]
+    fn a_cut_off_journal_replays_up_to_the_last_whole_edit() {
+        let dir = scratch_dir("recovery-truncated");
+        let (mut recovery, mut editor) = recording(&dir, "a");
+        type_keys(&mut recovery, &mut editor, "b");
+        type_keys(&mut recovery, &mut editor, "c");
+        let journal = fs::read(&recovery.journal_path).unwrap();
+        for cut in 1..4 {
+            fs::write(&recovery.journal_path, &journal[..journal.len() - cut]).unwrap();
+            assert_eq!(recovery.load().unwrap().as_deref(), Some("ab"));
+        }
+
+        let mut corrupt = journal.clone();
+        corrupt.extend_from_slice(b"+99 1\nd\n");
+        fs::write(&recovery.journal_path, &corrupt).unwrap();
+        assert_eq!(recovery.load().unwrap().as_deref(), Some("abc"));
+        corrupt.truncate(journal.len());
+        corrupt.extend_from_slice(b"?1 1\nd\n+1 1\ne\n");
+        fs::write(&recovery.journal_path, &corrupt).unwrap();
+        assert_eq!(recovery.load().unwrap().as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn a_journal_from_another_snapshot_is_ignored() {
+        let dir = scratch_dir("recovery-stale");
+        let (mut recovery, mut editor) = recording(&dir, "a");
+        type_keys(&mut recovery, &mut editor, "b");
+        fs::write(&recovery.snapshot_path, "x").unwrap();
+        assert_eq!(recovery.load().unwrap().as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn files_are_named_after_the_absolute_path() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        let dir = Path::new("/recovery");
+        let recovery = Recovery::new(dir, Path::new("/notes/todo.txt"));
+        let name = format!("{:016x}", fnv1a(b"/notes/todo.txt"));
+        assert_eq!(recovery.snapshot_path, dir.join(format!("{name}.txt")));
+        assert_eq!(recovery.journal_path, dir.join(format!("{name}.journal")));
+        let other = Recovery::new(dir, Path::new("/notes/done.txt"));
+        assert_ne!(other.snapshot_path, recovery.snapshot_path);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::{
+    atlas::Atlas,
+    editor::Editor,
+    layout::{PageRange, vertex_t},
+    maths::Float2,
+};
+
+pub mod software;
+
+//what a frontend needs from a gpu (or not), a frame is any number of draws followed by present
+//draws happen in call order, so rects drawn before the glyphs end up behind them
+pub trait Renderer {
+    //rebuild whatever pages changed since the last upload
+    fn upload_atlas(&mut self, atlas: &Atlas);
+    //six verts and one tex pointer per glyph, grouped by page as layout returns them
+    fn upload_glyph_instances(
+        &mut self,
+        verts: &[vertex_t],
+        tex_pointers: &[Float2],
+        page_ranges: &[PageRange],
+    );
+    //the last uploaded glyph instances, through text_fragment
+    fn draw_glyphs(&mut self);
+    //flat colored triangles, through cursor_fragment
+    fn draw_rects(&mut self, verts: &[vertex_t]);
+    fn present(&mut self);
+}
+
+//one whole frame, uploading everything first, frontends that track changes can skip uploads
+pub fn draw_editor<R: Renderer>(renderer: &mut R, editor: &mut Editor) {
+    renderer.upload_atlas(editor.atlas());
+    renderer.upload_glyph_instances(
+        editor.text_verts(),
+        editor.tex_pointers(),
+        editor.page_ranges(),
+    );
+    renderer.draw_rects(&editor.selection_verts());
+    renderer.draw_glyphs();
+    renderer.draw_rects(&editor.cursor_verts());
+    renderer.present();
+}
@@ -0,0 +1,361 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    atlas::Atlas,
+    layout::{PageRange, vertex_t},
+    maths::{Float2, Float4},
+    render::Renderer,
+};
+
+//cpu version of the metal pipeline, takes the same verts and atlas pages and does the
+//same blending into an rgba8 framebuffer, so frames can be rendered without a gpu
+pub struct SoftwareRenderer {
+    framebuffer: Framebuffer,
+    screen_size: Float2,
+    clear_color: Float4,
+    //the first draw after a present clears, so pixels() keeps the last presented frame until then
+    frame_started: bool,
+    //page image and the atlas generation it was built from
+    pages: Vec<(PageImage, u64)>,
+    glyph_verts: Vec<vertex_t>,
+    tex_pointers: Vec<Float2>,
+    page_ranges: Vec<PageRange>,
+}
+
+impl SoftwareRenderer {
+    //screen_size is the uniform box_vertex divides positions by, width/height are in pixels
+    pub fn new(width: u32, height: u32, screen_size: Float2) -> Self {
+        Self {
+            framebuffer: Framebuffer {
+                width,
+                height,
+                pixels: vec![0; (width * height * 4) as usize],
+            },
+            screen_size,
+            //same as the metal render pass, the window behind it shows through
+            clear_color: Float4(0.0, 0.0, 0.0, 0.0),
+            frame_started: false,
+            pages: Vec::new(),
+            glyph_verts: Vec::new(),
+            tex_pointers: Vec::new(),
+            page_ranges: Vec::new(),
+        }
+    }
+
+    pub fn set_clear_color(&mut self, color: Float4) {
+        self.clear_color = color;
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.framebuffer.width, self.framebuffer.height)
+    }
+
+    //rgba8, rows top to bottom
+    pub fn pixels(&self) -> &[u8] {
+        &self.framebuffer.pixels
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder =
+            png::Encoder::new(writer, self.framebuffer.width, self.framebuffer.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.framebuffer.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    fn start_frame(&mut self) {
+        if self.frame_started {
+            return;
+        }
+        self.frame_started = true;
+        let Float4(r, g, b, a) = self.clear_color;
+        let color = [r, g, b, a].map(to_unorm);
+        for pixel in self.framebuffer.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    //same as the metal side, only pages whose generation changed get rebuilt
+    fn upload_atlas(&mut self, atlas: &Atlas) {
+        for (index, page) in atlas.pages().iter().enumerate() {
+            if let Some((_, generation)) = self.pages.get(index)
+                && *generation == page.generation()
+            {
+                continue;
+            }
+            let image = (PageImage::from_atlas(atlas, index), page.generation());
+            match index < self.pages.len() {
+                true => self.pages[index] = image,
+                false => self.pages.push(image),
+            }
+        }
+    }
+
+    fn upload_glyph_instances(
+        &mut self,
+        verts: &[vertex_t],
+        tex_pointers: &[Float2],
+        page_ranges: &[PageRange],
+    ) {
+        self.glyph_verts = verts.to_vec();
+        self.tex_pointers = tex_pointers.to_vec();
+        self.page_ranges = page_ranges.to_vec();
+    }
+
+    fn draw_glyphs(&mut self) {
+        self.start_frame();
+        for range in &self.page_ranges {
+            let (page, _) = &self.pages[range.page];
+            for instance in range.start..range.start + range.count {
+                let tex_pointer = self.tex_pointers[instance];
+                for triangle in self.glyph_verts[instance * 6..instance * 6 + 6].chunks_exact(3) {
+                    self.framebuffer
+                        .rasterize(triangle, self.screen_size, |uv, color| {
+                            Float4(color.0, color.1, color.2, page.sample(uv + tex_pointer))
+                        });
+                }
+            }
+        }
+    }
+
+    fn draw_rects(&mut self, verts: &[vertex_t]) {
+        self.start_frame();
+        for triangle in verts.chunks_exact(3) {
+            self.framebuffer
+                .rasterize(triangle, self.screen_size, |_, color| color);
+        }
+    }
+
+    //nothing to swap, the frame just stays in pixels() until the next draw
+    fn present(&mut self) {
+        self.start_frame();
+        self.frame_started = false;
+    }
+}
+
+struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    //fills pixels whose centers are inside the triangle, shared edges follow the top-left rule
+    //so the two halves of a quad don't blend the diagonal twice
+    fn rasterize<F: Fn(Float2, Float4) -> Float4>(
+        &mut self,
+        triangle: &[vertex_t],
+        screen_size: Float2,
+        fragment: F,
+    ) {
+        //same mapping as box_vertex, then ndc to pixels with y pointing down
+        let to_pixels = |vert: &vertex_t| {
+            Float2(
+                (vert.position.0 / screen_size.0 + 1.0) * 0.5 * self.width as f32,
+                (1.0 - vert.position.1 / screen_size.1) * 0.5 * self.height as f32,
+            )
+        };
+        let mut verts = [&triangle[0], &triangle[1], &triangle[2]];
+        let mut points = verts.map(to_pixels);
+        let mut area = edge(points[0], points[1], points[2]);
+        if area == 0.0 {
+            return;
+        }
+        if area < 0.0 {
+            verts.swap(1, 2);
+            points.swap(1, 2);
+            area = -area;
+        }
+
+        let min_x = points.iter().map(|p| p.0).fold(f32::INFINITY, f32::min);
+        let max_x = points.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+        let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+        let x_range = (min_x.floor().max(0.0) as u32)..(max_x.ceil().min(self.width as f32) as u32);
+        let y_range =
+            (min_y.floor().max(0.0) as u32)..(max_y.ceil().min(self.height as f32) as u32);
+
+        let edges = [(1, 2), (2, 0), (0, 1)];
+        for y in y_range {
+            for x in x_range.clone() {
+                let center = Float2(x as f32 + 0.5, y as f32 + 0.5);
+                let weights = edges.map(|(a, b)| edge(points[a], points[b], center));
+                let covered = edges.iter().zip(weights).all(|(&(a, b), weight)| {
+                    weight > 0.0 || (weight == 0.0 && is_top_left(points[a], points[b]))
+                });
+                if !covered {
+                    continue;
+                }
+                let weights = weights.map(|weight| weight / area);
+                let uv = verts
+                    .iter()
+                    .zip(weights)
+                    .fold(Float2(0.0, 0.0), |uv, (vert, weight)| {
+                        uv + vert.uv.xy() * weight
+                    });
+                let color = verts.iter().zip(weights).fold(
+                    Float4(0.0, 0.0, 0.0, 0.0),
+                    |color, (vert, weight)| {
+                        Float4(
+                            color.0 + vert.color.0 * weight,
+                            color.1 + vert.color.1 * weight,
+                            color.2 + vert.color.2 * weight,
+                            color.3 + vert.color.3 * weight,
+                        )
+                    },
+                );
+                self.blend(x, y, fragment(uv, color));
+            }
+        }
+    }
+
+    //source alpha / one minus source alpha on both rgb and alpha, like the pipeline attachment
+    fn blend(&mut self, x: u32, y: u32, source: Float4) {
+        let index = ((y * self.width + x) * 4) as usize;
+        let pixel = &mut self.pixels[index..index + 4];
+        let alpha = source.3.clamp(0.0, 1.0);
+        let source = [source.0, source.1, source.2, alpha];
+        for (channel, source) in pixel.iter_mut().zip(source) {
+            let destination = *channel as f32 / 255.0;
+            *channel = to_unorm(source.clamp(0.0, 1.0) * alpha + destination * (1.0 - alpha));
+        }
+    }
+}
+
+//single channel copy of an atlas page, glyph bitmaps at their packed positions
+struct PageImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl PageImage {
+    fn from_atlas(atlas: &Atlas, page: usize) -> Self {
+        let (width, height) = atlas.pages()[page].packer.size();
+        let mut pixels = vec![0; (width * height) as usize];
+        for glyph in atlas.glyphs_on_page(page) {
+            let glyph_width = glyph.data.width as usize;
+            if glyph_width == 0 {
+                continue;
+            }
+            let (x, y) = glyph.tex_pointer();
+            for (row, bitmap_row) in glyph.data.bitmap.chunks_exact(glyph_width).enumerate() {
+                let start = (y as usize + row) * width as usize + x as usize;
+                pixels[start..start + glyph_width].copy_from_slice(bitmap_row);
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    //linear filtering in pixel coordinates with clamp_to_zero addressing, like text_fragment's sampler
+    fn sample(&self, uv: Float2) -> f32 {
+        let (x, y) = (uv.0 - 0.5, uv.1 - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |x: f32, y: f32| {
+            if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+                return 0.0;
+            }
+            self.pixels[y as usize * self.width as usize + x as usize] as f32 / 255.0
+        };
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+//twice the signed area of abc, positive when c is clockwise from ab on screen
+fn edge(a: Float2, b: Float2, c: Float2) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn is_top_left(a: Float2, b: Float2) -> bool {
+    (a.1 == b.1 && b.0 > a.0) || b.1 < a.1
+}
+
+fn to_unorm(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use freetype::Library;
+
+    use super::*;
+    use crate::{
+        buffer::TextBuffer,
+        editor::{
+            Editor,
+            tests::{SHIFT, press_keys},
+        },
+        keymap::Key,
+        render::draw_editor,
+        text::{
+            FontChain,
+            loader::{FontLoader, FontSource},
+        },
+    };
+
+    const GOLDEN: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/render/testdata/editor.png"
+    );
+
+    //one screen unit per pixel, so the text is drawn at its real size
+    fn render(text: &str) -> SoftwareRenderer {
+        let lib = Library::init().unwrap();
+        let font = FontLoader::new(&lib)
+            .load(&FontSource::Embedded, 16)
+            .unwrap();
+        let atlas = Atlas::new(&font.face).unwrap();
+        let mut editor = Editor::new(
+            TextBuffer::from(text),
+            atlas,
+            FontChain::new(font),
+            16,
+            Float2(-156.0, 36.0),
+            312.0,
+            Float4(0.0, 0.0, 0.0, 1.0),
+        );
+        press_keys(&mut editor, &[Key::Left; 3], SHIFT);
+        let mut renderer = SoftwareRenderer::new(320, 80, Float2(160.0, 40.0));
+        renderer.set_clear_color(Float4(1.0, 1.0, 1.0, 1.0));
+        draw_editor(&mut renderer, &mut editor);
+        renderer
+    }
+
+    //UPDATE_GOLDEN=1 cargo test rewrites the reference after an intended change
+    #[test]
+    fn editor_frame_matches_the_reference() {
+        let renderer = render("Hello, world!\rtxt-rs → AV\u{301}");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            renderer.save_png(GOLDEN).unwrap();
+        }
+        let decoder = png::Decoder::new(io::BufReader::new(File::open(GOLDEN).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut expected = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut expected).unwrap();
+        assert_eq!((info.width, info.height), renderer.size());
+        assert!(
+            expected[..info.buffer_size()] == *renderer.pixels(),
+            "frame differs from {GOLDEN}"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::ops::Range;
+
+//anchor stays put while the head moves, an empty selection is just a caret at head
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+    //x the head is trying to get back to when moving up/down through shorter lines
+    pub goal_x: Option<f32>,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self {
+            anchor,
+            head,
+            goal_x: None,
+        }
+    }
+
+    pub fn caret(index: usize) -> Self {
+        Self::new(index, index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start()..self.end()
+    }
+
+    //moves the head, keeping the anchor when extending
+    pub fn move_to(&mut self, index: usize, extend: bool) {
+        self.head = index;
+        self.goal_x = None;
+        if !extend {
+            self.anchor = index;
+        }
+    }
+}
+
+//every caret/selection in the editor, kept sorted and non-overlapping
+//primary is the one the view follows and the one kept when collapsing back to a single cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selections {
+    selections: Vec<Selection>,
+    primary: usize,
+}
+
+impl Selections {
+    pub fn single(selection: Selection) -> Self {
+        Self {
+            selections: vec![selection],
+            primary: 0,
+        }
+    }
+
+    pub fn primary(&self) -> Selection {
+        self.selections[self.primary]
+    }
+
+    pub fn as_slice(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    pub fn len(&self) -> usize {
+        self.selections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selections.is_empty()
+    }
+
+    //new selection becomes the primary
+    pub fn add(&mut self, selection: Selection) {
+        self.selections.push(selection);
+        self.primary = self.selections.len() - 1;
+        self.normalize();
+    }
+
+    pub fn collapse_to_primary(&mut self) {
+        *self = Selections::single(self.primary());
+    }
+
+    pub fn map<F: FnMut(Selection) -> Selection>(&mut self, f: F) {
+        self.selections = self.selections.iter().copied().map(f).collect();
+        self.normalize();
+    }
+
+    //replaces every selection, in the same order as they were, so the primary keeps its index
+    pub fn replace_all(&mut self, selections: Vec<Selection>) {
+        debug_assert_eq!(selections.len(), self.selections.len());
+        self.selections = selections;
+        self.normalize();
+    }
+
+    //sorts and merges anything overlapping or touching, the merged one keeps the first's direction
+    fn normalize(&mut self) {
+        let primary = self.selections[self.primary];
+        let mut selections = std::mem::take(&mut self.selections);
+        selections.sort_by_key(|selection| (selection.start(), selection.end()));
+        self.primary = 0;
+        for selection in selections {
+            let is_primary = selection == primary;
+            match self.selections.last_mut() {
+                Some(last) if selection.start() <= last.end() => {
+                    let (start, end) = (last.start(), last.end().max(selection.end()));
+                    *last = match last.head >= last.anchor {
+                        true => Selection::new(start, end),
+                        false => Selection::new(end, start),
+                    };
+                }
+                _ => self.selections.push(selection),
+            }
+            if is_primary {
+                self.primary = self.selections.len() - 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_to_keeps_the_anchor_when_extending() {
+        let mut selection = Selection::caret(4);
+        selection.move_to(1, true);
+        assert_eq!((selection.anchor, selection.head), (4, 1));
+        assert_eq!(selection.range(), 1..4);
+        selection.move_to(2, false);
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn added_selections_are_sorted_and_primary() {
+        let mut selections = Selections::single(Selection::caret(5));
+        selections.add(Selection::caret(1));
+        assert_eq!(selections.as_slice()[0], Selection::caret(1));
+        assert_eq!(selections.primary(), Selection::caret(1));
+        selections.collapse_to_primary();
+        assert_eq!(selections.as_slice(), [Selection::caret(1)]);
+    }
+
+    #[test]
+    fn overlapping_selections_merge() {
+        let mut selections = Selections::single(Selection::new(6, 2));
+        selections.add(Selection::caret(9));
+        selections.add(Selection::new(4, 8));
+        assert_eq!(
+            selections.as_slice(),
+            [Selection::new(8, 2), Selection::caret(9)]
+        );
+        assert_eq!(selections.primary(), Selection::new(8, 2));
+        //carets moving onto each other become one
+        selections.map(|_| Selection::caret(0));
+        assert_eq!(selections.len(), 1);
+    }
+}
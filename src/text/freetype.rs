@@ -1,9 +1,14 @@
+use std::rc::Rc;
+
 use freetype::{
     Face, FtResult, Library,
+    bitmap::PixelMode,
     face::LoadFlag,
     ffi::{FT_BBox, FT_Vector},
 };
 
+use crate::text::Font;
+
 pub struct GlyphData {
     pub bitmap: Vec<u8>,
     pub width: u64,
@@ -12,17 +17,30 @@ pub struct GlyphData {
     pub cbox: FT_BBox,
 }
 
-pub fn init_typeface_with_size(lib: &Library, name: &str, size: u32) -> FtResult<Face> {
-    // let filepath = format!("./resources/{name}");
-    let filepath = format!("/Users/basil/rust-projects/txt-rs/resources/{name}");
-    let face = lib.new_face(filepath, 0)?;
-    face.set_pixel_sizes(size, size)?;
-    Ok(face)
+//face is loaded from memory so the same bytes can be handed to the shaper
+pub fn load_font_data(
+    lib: &Library,
+    data: Rc<Vec<u8>>,
+    face_index: u32,
+    size: u32,
+) -> FtResult<Font> {
+    let face = lib.new_memory_face(data.clone(), face_index as isize)?;
+    //bitmap only faces (color emoji) can't be scaled, use their first strike instead
+    if face.is_scalable() || !face.has_fixed_sizes() {
+        face.set_pixel_sizes(size, size)?;
+    } else {
+        face.select_size(0)?;
+    }
+    Ok(Font::new(face, data, face_index))
 }
 
-pub fn get_char_glyph(face: &Face, character: char) -> FtResult<GlyphData> {
-    face.load_char(character as usize, LoadFlag::RENDER)?;
+//glyph ids come from the shaper, so glyphs are rasterized by id rather than by char
+pub fn get_glyph(face: &Face, glyph_id: u32) -> FtResult<GlyphData> {
+    face.load_glyph(glyph_id, LoadFlag::RENDER | LoadFlag::COLOR)?;
+    loaded_glyph_data(face)
+}
 
+fn loaded_glyph_data(face: &Face) -> FtResult<GlyphData> {
     let slot = face.glyph();
     let bitmap = slot.bitmap();
     let width = slot.bitmap().width();
@@ -31,11 +49,14 @@ pub fn get_char_glyph(face: &Face, character: char) -> FtResult<GlyphData> {
 
     let vec = if width == 0 || height == 0 {
         vec![]
+    } else if let Ok(PixelMode::Bgra) = bitmap.pixel_mode() {
+        //atlas is single channel, color glyphs (emoji) keep just their coverage
+        bitmap.buffer().chunks(4).map(|pixel| pixel[3]).collect()
     } else {
         bitmap.buffer().to_vec()
     };
 
-    let cbox = slot.get_glyph().unwrap().get_cbox(3); //3 is FT_GLYPH_BBOX_PIXELS
+    let cbox = slot.get_glyph()?.get_cbox(3); //3 is FT_GLYPH_BBOX_PIXELS
     Ok(GlyphData {
         bitmap: vec,
         width: width as u64,
@@ -0,0 +1,192 @@
+use std::{fmt, path::PathBuf, rc::Rc};
+
+use fontdb::{Database, Family, Query, Source};
+use freetype::Library;
+use serde::Deserialize;
+
+use crate::text::{Font, freetype::load_font_data};
+
+//bundled so there's always a usable font, no matter where the binary runs from
+pub const EMBEDDED_FONT: &[u8] = include_bytes!("../../resources/Arial.ttf");
+
+//where to get a font from, written in the config as e.g. { system = "Helvetica" }
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSource {
+    Path(PathBuf),
+    //file name inside a resources folder, see resource_path
+    Resource(String),
+    //family name looked up in the fonts found by scanning the system's font directories
+    System(String),
+    Embedded,
+}
+
+impl fmt::Display for FontSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontSource::Path(path) => write!(f, "font file {}", path.display()),
+            FontSource::Resource(name) => write!(f, "resource font {name}"),
+            FontSource::System(family) => write!(f, "system font family \"{family}\""),
+            FontSource::Embedded => write!(f, "embedded font"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    NotFound(FontSource),
+    Io(PathBuf, std::io::Error),
+    FreeType(FontSource, freetype::Error),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::NotFound(source) => write!(f, "could not find {source}"),
+            FontError::Io(path, err) => write!(f, "could not read {}: {err}", path.display()),
+            FontError::FreeType(source, err) => write!(f, "could not load {source}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+pub struct FontLoader<'a> {
+    lib: &'a Library,
+    //fontdb rather than fontconfig on purpose, macos doesn't have fontconfig and fontdb scans
+    //the font directories itself with no c library to link
+    //scanning is slow-ish so it only happens once, on the first system lookup
+    system_fonts: Option<Database>,
+}
+
+impl<'a> FontLoader<'a> {
+    pub fn new(lib: &'a Library) -> Self {
+        Self {
+            lib,
+            system_fonts: None,
+        }
+    }
+
+    pub fn load(&mut self, source: &FontSource, size: u32) -> Result<Font, FontError> {
+        let (data, face_index) = match source {
+            FontSource::Path(path) => (read_font(path.clone())?, 0),
+            FontSource::Resource(name) => {
+                let path =
+                    resource_path(name).ok_or_else(|| FontError::NotFound(source.clone()))?;
+                (read_font(path)?, 0)
+            }
+            FontSource::System(family) => self.find_system_font(family, source)?,
+            FontSource::Embedded => (EMBEDDED_FONT.to_vec(), 0),
+        };
+        load_font_data(self.lib, Rc::new(data), face_index, size)
+            .map_err(|err| FontError::FreeType(source.clone(), err))
+    }
+
+    fn find_system_font(
+        &mut self,
+        family: &str,
+        source: &FontSource,
+    ) -> Result<(Vec<u8>, u32), FontError> {
+        let database = self.system_fonts.get_or_insert_with(|| {
+            let mut database = Database::new();
+            database.load_system_fonts();
+            database
+        });
+        let query = Query {
+            families: &[Family::Name(family)],
+            ..Default::default()
+        };
+        let (font_source, face_index) = database
+            .query(&query)
+            .and_then(|id| database.face_source(id))
+            .ok_or_else(|| FontError::NotFound(source.clone()))?;
+        let data = match font_source {
+            Source::File(path) | Source::SharedFile(path, _) => read_font(path)?,
+            Source::Binary(data) => (*data).as_ref().to_vec(),
+        };
+        Ok((data, face_index))
+    }
+}
+
+//next to the binary, then the working directory, and the source checkout only for dev builds
+//so a release binary never depends on where it was built
+fn resource_path(name: &str) -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from));
+    let mut dirs: Vec<PathBuf> = exe_dir
+        .into_iter()
+        .chain(std::env::current_dir().ok())
+        .collect();
+    if cfg!(debug_assertions) {
+        dirs.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join("resources").join(name))
+        .find(|path| path.exists())
+}
+
+fn read_font(path: PathBuf) -> Result<Vec<u8>, FontError> {
+    std::fs::read(&path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => FontError::NotFound(FontSource::Path(path)),
+        _ => FontError::Io(path, err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a loader whose "system" is just the test font, so it doesn't depend on what's installed
+    fn loader(lib: &Library) -> FontLoader<'_> {
+        let mut database = Database::new();
+        database
+            .load_font_file(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/text/testdata/Tuffy.ttf"
+            ))
+            .unwrap();
+        FontLoader {
+            lib,
+            system_fonts: Some(database),
+        }
+    }
+
+    #[test]
+    fn system_families_load_the_matching_face() {
+        let lib = Library::init().unwrap();
+        let font = loader(&lib)
+            .load(&FontSource::System("Tuffy".to_string()), 20)
+            .unwrap();
+        assert_eq!(font.face.family_name().as_deref(), Some("Tuffy"));
+    }
+
+    #[test]
+    fn missing_fonts_are_not_found() {
+        let lib = Library::init().unwrap();
+        let mut loader = loader(&lib);
+        let missing = [
+            FontSource::System("No Such Family".to_string()),
+            FontSource::Resource("missing.ttf".to_string()),
+            FontSource::Path(PathBuf::from("/no/such/font.ttf")),
+        ];
+        for source in missing {
+            let result = loader.load(&source, 20);
+            assert!(matches!(result, Err(FontError::NotFound(_))), "{source}");
+        }
+    }
+
+    #[test]
+    fn resources_and_paths_load_from_files() {
+        let lib = Library::init().unwrap();
+        let mut loader = loader(&lib);
+        //dev builds fall back to the checkout's resources folder
+        let resource = loader
+            .load(&FontSource::Resource("Arial.ttf".to_string()), 20)
+            .unwrap();
+        assert_eq!(resource.data(), EMBEDDED_FONT);
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/Arial.ttf");
+        let font = loader.load(&FontSource::Path(path), 20).unwrap();
+        assert_eq!(font.face.family_name().as_deref(), Some("Arial"));
+    }
+}
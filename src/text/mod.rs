@@ -1 +1,85 @@
+use std::rc::Rc;
+
+use ::freetype::Face;
+
 pub mod freetype;
+pub mod loader;
+pub mod shaping;
+
+//freetype face for rasterizing plus the raw font bytes rustybuzz shapes from
+pub struct Font {
+    pub face: Face,
+    data: Rc<Vec<u8>>,
+    face_index: u32,
+}
+
+impl Font {
+    pub fn new(face: Face, data: Rc<Vec<u8>>, face_index: u32) -> Font {
+        Font {
+            face,
+            data,
+            face_index,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn face_index(&self) -> u32 {
+        self.face_index
+    }
+
+    //pixels per font unit at the face's current pixel size
+    pub fn scale(&self) -> f32 {
+        let ppem = self
+            .face
+            .size_metrics()
+            .map(|metrics| metrics.x_ppem)
+            .unwrap_or_default();
+        ppem as f32 / self.face.em_size().max(1) as f32
+    }
+}
+
+//ordered fonts consulted per char, primary first, then symbol, cjk, emoji etc
+pub struct FontChain {
+    fonts: Vec<Font>,
+}
+
+impl FontChain {
+    pub fn new(primary: Font) -> FontChain {
+        FontChain {
+            fonts: vec![primary],
+        }
+    }
+
+    pub fn push(&mut self, font: Font) {
+        self.fonts.push(font);
+    }
+
+    pub fn primary(&self) -> &Font {
+        &self.fonts[0]
+    }
+
+    pub fn get(&self, index: usize) -> &Font {
+        &self.fonts[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    //first font in the chain that has a glyph for char, primary is used if nothing covers it
+    //(renders .notdef), layout picks by a grapheme's first char so marks, joiners and
+    //selectors get shaped in the same font as what they attach to
+    pub fn font_for_char(&self, char: char) -> usize {
+        self.fonts
+            .iter()
+            .position(|font| font.face.get_char_index(char as usize).is_some())
+            .unwrap_or(0)
+    }
+}
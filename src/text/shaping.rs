@@ -0,0 +1,42 @@
+use rustybuzz::UnicodeBuffer;
+
+use crate::{maths::Float2, text::Font};
+
+//glyph positioned by the shaper, all measurements in pixels
+pub struct ShapedGlyph {
+    pub glyph_id: u32,
+    //char offset into the shaped text where this glyph's cluster starts,
+    //ligatures and base + mark sequences share a cluster
+    pub cluster: usize,
+    pub advance: Float2,
+    pub offset: Float2,
+}
+
+//runs harfbuzz style shaping over a single line of text, so kerning (kern/GPOS),
+//standard ligatures and mark positioning all come from the font's own tables
+pub fn shape_text(font: &Font, text: &str) -> Vec<ShapedGlyph> {
+    let Some(shaping_face) = rustybuzz::Face::from_slice(font.data(), font.face_index()) else {
+        return Vec::new();
+    };
+    let scale = font.scale();
+
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    let glyph_buffer = rustybuzz::shape(&shaping_face, &[], buffer);
+
+    //clusters come back as byte offsets
+    let char_starts: Vec<usize> = text.char_indices().map(|(byte, _)| byte).collect();
+    glyph_buffer
+        .glyph_infos()
+        .iter()
+        .zip(glyph_buffer.glyph_positions())
+        .map(|(info, position)| ShapedGlyph {
+            glyph_id: info.glyph_id,
+            cluster: char_starts
+                .binary_search(&(info.cluster as usize))
+                .unwrap_or_else(|index| index),
+            advance: Float2(position.x_advance as f32, position.y_advance as f32) * scale,
+            offset: Float2(position.x_offset as f32, position.y_offset as f32) * scale,
+        })
+        .collect()
+}
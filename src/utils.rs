@@ -11,6 +11,7 @@ use objc2_app_kit::{
     NSWindowStyleMask,
 };
 use objc2_foundation::{CGPoint, MainThreadMarker, NSDate, NSRect, NSSize, NSString};
+use txt_rs::atlas::Atlas;
 
 //Metal utils
 
@@ -95,15 +96,19 @@ pub fn make_buf<T>(data: &Vec<T>, device: &DeviceRef) -> Buffer {
         MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
     )
 }
-pub fn make_buf_with_capacity<T>(data: &Vec<T>, max_len: usize, device: &DeviceRef) -> Buffer {
-    device.new_buffer_with_data(
-        data.as_ptr() as *const _,
+//room for max_len elements, only data's part of it gets filled
+pub fn make_buf_with_capacity<T>(data: &[T], max_len: usize, device: &DeviceRef) -> Buffer {
+    let buf = device.new_buffer(
         (mem::size_of::<T>() * max_len) as u64,
         MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
-    )
+    );
+    if !data.is_empty() {
+        copy_to_buf(data, &buf);
+    }
+    buf
 }
 
-pub fn copy_to_buf<T>(data: &Vec<T>, dst: &Buffer) {
+pub fn copy_to_buf<T>(data: &[T], dst: &Buffer) {
     let buf_pointer = dst.contents();
     unsafe {
         std::ptr::copy(data.as_ptr(), buf_pointer as *mut T, data.len() as usize);
@@ -111,6 +116,47 @@ pub fn copy_to_buf<T>(data: &Vec<T>, dst: &Buffer) {
     dst.did_modify_range(NSRange::new(0 as u64, (data.len() * size_of::<T>()) as u64));
 }
 
+//one texture per atlas page, rebuilds any page whose generation changed since the last sync
+pub fn sync_atlas_textures(atlas: &Atlas, textures: &mut Vec<(Texture, u64)>, device: &DeviceRef) {
+    for (index, page) in atlas.pages().iter().enumerate() {
+        if let Some((_, generation)) = textures.get(index)
+            && *generation == page.generation()
+        {
+            continue;
+        }
+        let texture = (
+            new_atlas_page_texture(atlas, index, device),
+            page.generation(),
+        );
+        match index < textures.len() {
+            true => textures[index] = texture,
+            false => textures.push(texture),
+        }
+    }
+}
+
+//fresh texture so pixels from evicted glyphs don't bleed into their replacements
+fn new_atlas_page_texture(atlas: &Atlas, page: usize, device: &DeviceRef) -> Texture {
+    let (width, height) = atlas.pages()[page].packer.size();
+    let atlas_descriptor = TextureDescriptor::new();
+    atlas_descriptor.set_pixel_format(MTLPixelFormat::R8Unorm);
+    atlas_descriptor.set_width(width as u64);
+    atlas_descriptor.set_height(height as u64);
+
+    let texture = device.new_texture(&atlas_descriptor);
+
+    for glyph in atlas.glyphs_on_page(page) {
+        let (width, height) = (glyph.data.width, glyph.data.height);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let (x, y) = glyph.tex_pointer();
+        let region = MTLRegion::new_2d(x as u64, y as u64, width, height);
+        texture.replace_region(region, 0, glyph.data.bitmap.as_ptr() as *const _, width);
+    }
+    texture
+}
+
 // pub fn prepare_compute_state(device: &DeviceRef) {
 //     let descriptor = ComputePassDescriptor::new();
 //     descriptor.set_dispatch_type(MTLDis);
@@ -0,0 +1,541 @@
+use std::mem;
+
+use crate::{
+    command::{ControlCommand, KeyCommand, Modifiers, Motion, TextCommand},
+    keymap::{Key, Keymap},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+//repeats are clamped to this, a count like 999999999j would otherwise build that many
+//commands, G and gg still see the whole count as a line number
+const MAX_COUNT: usize = 10_000;
+
+//modal layer in front of the keymap, turns vim keys into the editor's own commands
+//the unnamed register is the clipboard, so d and y copy and p pastes from it
+pub struct Vim {
+    mode: VimMode,
+    count: Option<usize>,
+    //with the count typed before it, e.g. the 2 in 2d3w
+    operator: Option<(Operator, usize)>,
+    pending_g: bool,
+    //keys of the command being typed, and of the last one that changed the text for `.`
+    keys: Vec<(Key, Modifiers)>,
+    last_change: Vec<(Key, Modifiers)>,
+    //the last d or y was dd or yy, so p puts the clipboard on lines of its own
+    linewise: bool,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: VimMode::Normal,
+            count: None,
+            operator: None,
+            pending_g: false,
+            keys: Vec::new(),
+            last_change: Vec::new(),
+            linewise: false,
+        }
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    //commands to run in order, empty while a command is still being typed
+    pub fn commands_for(
+        &mut self,
+        key: Key,
+        modifiers: Modifiers,
+        keymap: &Keymap,
+    ) -> Vec<KeyCommand> {
+        self.keys.push((key, modifiers));
+        match self.mode {
+            VimMode::Insert => self.insert_key(key, modifiers, keymap),
+            VimMode::Normal | VimMode::Visual => self.normal_key(key, modifiers, keymap),
+        }
+    }
+
+    fn insert_key(&mut self, key: Key, modifiers: Modifiers, keymap: &Keymap) -> Vec<KeyCommand> {
+        if key == Key::Escape {
+            self.mode = VimMode::Normal;
+            self.finish(true);
+            return vec![move_to(Motion::LeftInLine)];
+        }
+        match keymap.command_for(key, modifiers) {
+            KeyCommand::Unknown => Vec::new(),
+            command => vec![command],
+        }
+    }
+
+    fn normal_key(&mut self, key: Key, modifiers: Modifiers, keymap: &Keymap) -> Vec<KeyCommand> {
+        let visual = self.mode == VimMode::Visual;
+        if modifiers.control && matches!(key, Key::Char('r' | 'R')) {
+            let count = self.take_count();
+            self.finish(false);
+            return vec![KeyCommand::CC(ControlCommand::Redo); count];
+        }
+        let key = match key {
+            Key::Escape => {
+                self.mode = VimMode::Normal;
+                self.finish(false);
+                return match visual {
+                    true => vec![move_to(Motion::Left)],
+                    false => Vec::new(),
+                };
+            }
+            Key::Char(char) if !modifiers.command && !modifiers.control => char,
+            //arrows, cmd chords and the like do what the keymap says, minus anything that types
+            _ => {
+                let command = match keymap.command_for(key, modifiers) {
+                    KeyCommand::CC(ControlCommand::Move(motion)) if visual => {
+                        KeyCommand::CC(ControlCommand::Select(motion))
+                    }
+                    command @ KeyCommand::CC(_) => command,
+                    _ => return Vec::new(),
+                };
+                self.count = None;
+                self.operator = None;
+                if !visual {
+                    self.keys.clear();
+                }
+                return vec![command];
+            }
+        };
+
+        if let Some(digit) = key.to_digit(10)
+            && (digit != 0 || self.count.is_some())
+            && !self.pending_g
+        {
+            self.count = Some(
+                self.count
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit as usize),
+            );
+            return Vec::new();
+        }
+
+        let motion = match mem::take(&mut self.pending_g) {
+            true if key == 'g' => Some(Motion::DocStart),
+            true => {
+                self.finish(false);
+                return Vec::new();
+            }
+            false => match key {
+                'h' => Some(Motion::LeftInLine),
+                'l' | ' ' => Some(Motion::RightInLine),
+                'j' => Some(Motion::Down),
+                'k' => Some(Motion::Up),
+                'w' => Some(Motion::NextWordStart),
+                'b' => Some(Motion::WordLeft),
+                'e' => Some(Motion::WordEnd),
+                '0' => Some(Motion::ParagraphStart),
+                '$' => Some(Motion::ParagraphEnd),
+                'G' => Some(Motion::DocEnd),
+                'g' => {
+                    self.pending_g = true;
+                    return Vec::new();
+                }
+                _ => None,
+            },
+        };
+        //a count on G or gg is the line to go to rather than a repeat
+        if let (Some(Motion::DocStart | Motion::DocEnd), Some(count)) = (motion, self.count)
+            && self.operator.is_none()
+            && !visual
+        {
+            self.finish(false);
+            return vec![KeyCommand::CC(ControlCommand::GoToLine(count.max(1) - 1))];
+        }
+        if let Some(motion) = motion {
+            let count = match motion {
+                Motion::DocStart | Motion::DocEnd => {
+                    self.count = None;
+                    1
+                }
+                _ => self.take_count(),
+            };
+            return match self.operator.take() {
+                Some((operator, operator_count)) => {
+                    self.apply_operator(operator, motion, (count * operator_count).min(MAX_COUNT))
+                }
+                None if visual => vec![KeyCommand::CC(ControlCommand::Select(motion)); count],
+                None => {
+                    self.finish(false);
+                    vec![move_to(motion); count]
+                }
+            };
+        }
+
+        let operator = match key {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            if visual {
+                self.mode = VimMode::Normal;
+                return self.operate(operator, Vec::new(), false);
+            }
+            let count = self.take_count();
+            return match self.operator.take() {
+                //doubled, dd cc yy work on whole lines
+                Some((pending, operator_count)) if pending == operator => {
+                    let lines = (count * operator_count).min(MAX_COUNT);
+                    match operator {
+                        //keeps the line itself, only its contents go
+                        Operator::Change => {
+                            let mut commands = vec![
+                                move_to(Motion::ParagraphStart),
+                                select(Motion::ParagraphEnd),
+                            ];
+                            commands.extend(vec![select(Motion::ParagraphDown); lines - 1]);
+                            self.operate(operator, commands, false)
+                        }
+                        _ => self.operate(
+                            operator,
+                            vec![KeyCommand::CC(ControlCommand::SelectLine); lines],
+                            true,
+                        ),
+                    }
+                }
+                Some(_) => {
+                    self.finish(false);
+                    Vec::new()
+                }
+                None => {
+                    self.operator = Some((operator, count));
+                    Vec::new()
+                }
+            };
+        }
+
+        //an operator followed by anything that isn't a motion is cancelled
+        if self.operator.take().is_some() {
+            self.finish(false);
+            return Vec::new();
+        }
+        let count = self.take_count();
+        match key {
+            'x' if visual => {
+                self.mode = VimMode::Normal;
+                self.operate(Operator::Delete, Vec::new(), false)
+            }
+            'x' => self.operate(
+                Operator::Delete,
+                vec![select(Motion::RightInLine); count],
+                false,
+            ),
+            'X' => self.operate(
+                Operator::Delete,
+                vec![select(Motion::LeftInLine); count],
+                false,
+            ),
+            'D' => self.operate(Operator::Delete, vec![select(Motion::ParagraphEnd)], false),
+            'C' => self.operate(Operator::Change, vec![select(Motion::ParagraphEnd)], false),
+            'p' | 'P' if self.linewise => {
+                self.finish(true);
+                vec![KeyCommand::CC(ControlCommand::PasteLines {
+                    above: key == 'P',
+                    count,
+                })]
+            }
+            'p' => {
+                self.finish(true);
+                let mut commands = vec![move_to(Motion::RightInLine)];
+                commands.extend(vec![KeyCommand::CC(ControlCommand::Paste); count]);
+                commands
+            }
+            'P' => {
+                self.finish(true);
+                vec![KeyCommand::CC(ControlCommand::Paste); count]
+            }
+            'i' => self.insert(Vec::new()),
+            'a' => self.insert(vec![move_to(Motion::RightInLine)]),
+            'I' => self.insert(vec![move_to(Motion::ParagraphStart)]),
+            'A' => self.insert(vec![move_to(Motion::ParagraphEnd)]),
+            'o' => self.insert(vec![
+                move_to(Motion::ParagraphEnd),
+                KeyCommand::TC(TextCommand::Newline),
+            ]),
+            'O' => self.insert(vec![
+                move_to(Motion::ParagraphStart),
+                KeyCommand::TC(TextCommand::Newline),
+                move_to(Motion::Left),
+            ]),
+            'u' => {
+                self.finish(false);
+                vec![KeyCommand::CC(ControlCommand::Undo); count]
+            }
+            'v' if visual => {
+                self.mode = VimMode::Normal;
+                self.finish(false);
+                vec![move_to(Motion::Left)]
+            }
+            //the char under the cursor is selected from the start, like vim's inclusive selection
+            'v' => {
+                self.mode = VimMode::Visual;
+                self.keys.clear();
+                self.keys.push((Key::Char(key), modifiers));
+                vec![select(Motion::RightInLine)]
+            }
+            '.' => {
+                let change = mem::take(&mut self.last_change);
+                self.keys.clear();
+                let mut commands = Vec::new();
+                for _ in 0..count {
+                    for &(key, modifiers) in &change {
+                        commands.extend(self.commands_for(key, modifiers, keymap));
+                    }
+                }
+                //replaying records the same keys again, unless it didn't get to finish
+                if self.last_change.is_empty() {
+                    self.last_change = change;
+                }
+                commands
+            }
+            _ => {
+                if !visual {
+                    self.finish(false);
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    //d and c cut motions over to their end, e is the one motion that includes its last char
+    fn apply_operator(
+        &mut self,
+        operator: Operator,
+        motion: Motion,
+        count: usize,
+    ) -> Vec<KeyCommand> {
+        //cw changes to the end of the word and leaves the space after it, same as ce
+        let motion = match (operator, motion) {
+            (Operator::Change, Motion::NextWordStart) => Motion::WordEnd,
+            (_, motion) => motion,
+        };
+        //dG and dgg take whole lines, cG and cgg keep the line they end on
+        if let Motion::DocStart | Motion::DocEnd = motion {
+            let commands = match operator {
+                Operator::Change => {
+                    let from = match motion {
+                        Motion::DocStart => Motion::ParagraphEnd,
+                        _ => Motion::ParagraphStart,
+                    };
+                    vec![move_to(from), select(motion)]
+                }
+                _ => vec![select(motion), KeyCommand::CC(ControlCommand::SelectLine)],
+            };
+            return self.operate(operator, commands, operator != Operator::Change);
+        }
+        let mut commands = vec![select(motion); count];
+        match motion {
+            Motion::WordEnd => commands.push(select(Motion::RightInLine)),
+            //only the last word moved over stops at the line end, like dw in vim
+            Motion::NextWordStart => {
+                commands.pop();
+                commands.push(select(Motion::NextWordStartInLine));
+            }
+            _ => {}
+        }
+        self.operate(operator, commands, false)
+    }
+
+    //selecting commands followed by the operator acting on whatever they selected,
+    //linewise for dd and yy, whose selections are whole lines
+    fn operate(
+        &mut self,
+        operator: Operator,
+        mut commands: Vec<KeyCommand>,
+        linewise: bool,
+    ) -> Vec<KeyCommand> {
+        let (cut, copy) = match linewise {
+            true => (ControlCommand::CutLines, ControlCommand::CopyLines),
+            false => (ControlCommand::Cut, ControlCommand::Copy),
+        };
+        self.linewise = linewise;
+        match operator {
+            Operator::Delete => {
+                commands.push(KeyCommand::CC(cut));
+                self.finish(true);
+            }
+            Operator::Yank => {
+                commands.push(KeyCommand::CC(copy));
+                commands.push(move_to(Motion::Left));
+                self.finish(false);
+            }
+            Operator::Change => {
+                commands.push(KeyCommand::CC(ControlCommand::Cut));
+                self.mode = VimMode::Insert;
+            }
+        }
+        commands
+    }
+
+    //the change keeps recording through insert mode until escape
+    fn insert(&mut self, commands: Vec<KeyCommand>) -> Vec<KeyCommand> {
+        self.mode = VimMode::Insert;
+        commands
+    }
+
+    fn take_count(&mut self) -> usize {
+        self.count.take().unwrap_or(1).min(MAX_COUNT)
+    }
+
+    fn finish(&mut self, changed: bool) {
+        self.count = None;
+        self.operator = None;
+        self.pending_g = false;
+        let keys = mem::take(&mut self.keys);
+        if changed {
+            self.last_change = keys;
+        }
+    }
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn move_to(motion: Motion) -> KeyCommand {
+    KeyCommand::CC(ControlCommand::Move(motion))
+}
+
+fn select(motion: Motion) -> KeyCommand {
+    KeyCommand::CC(ControlCommand::Select(motion))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::{
+        Editor,
+        tests::{ESCAPE, NONE, editor, press, text},
+    };
+
+    //starts at the top of the text, where gg puts it
+    fn vim(text: &str) -> Editor {
+        let mut editor = editor(text);
+        editor.set_vim_enabled(true);
+        press(&mut editor, "gg", NONE);
+        editor
+    }
+
+    fn keys(text: &str, keys: &str) -> Editor {
+        let mut editor = vim(text);
+        press(&mut editor, keys, NONE);
+        editor
+    }
+
+    #[test]
+    fn operators_with_motions() {
+        assert_eq!(text(&keys("one two three", "dw")), "two three");
+        assert_eq!(text(&keys("one two three", "d2w")), "three");
+        assert_eq!(text(&keys("one two three", "de")), " two three");
+        assert_eq!(text(&keys("one two\rthree", "wd$")), "one \rthree");
+        assert_eq!(text(&keys("abcdef", "3x")), "def");
+    }
+
+    #[test]
+    fn change_word_keeps_the_space() {
+        let editor = keys("one two", &format!("cwnew{ESCAPE}"));
+        assert_eq!(text(&editor), "new two");
+        assert_eq!(editor.index_in_text(), 2);
+    }
+
+    #[test]
+    fn dd_deletes_whole_lines() {
+        assert_eq!(text(&keys("a\rb\rc", "dd")), "b\rc");
+        assert_eq!(text(&keys("a\rb\rc", "2dd")), "c");
+        assert_eq!(text(&keys("a\rb\rc", "jdd")), "a\rc");
+    }
+
+    #[test]
+    fn dd_on_the_last_line_takes_the_newline_before_it() {
+        let editor = keys("a\rb\rc", "Gdd");
+        assert_eq!(text(&editor), "a\rb");
+        assert_eq!(editor.index_in_text(), 2);
+        assert_eq!(text(&keys("only", "dd")), "");
+    }
+
+    #[test]
+    fn linewise_yanks_paste_on_their_own_lines() {
+        let editor = keys("hello\rworld", "yyp");
+        assert_eq!(text(&editor), "hello\rhello\rworld");
+        assert_eq!(editor.index_in_text(), 6);
+        let editor = keys("hello\rworld", "jyyP");
+        assert_eq!(text(&editor), "hello\rworld\rworld");
+        assert_eq!(editor.index_in_text(), 6);
+        assert_eq!(text(&keys("a\rb", "jyyp")), "a\rb\rb");
+        assert_eq!(text(&keys("a\rb", "ddp")), "b\ra");
+        assert_eq!(text(&keys("a\rb\rc", "2yyGp")), "a\rb\rc\ra\rb");
+        assert_eq!(text(&keys("a\rb", "yy2p")), "a\ra\ra\rb");
+    }
+
+    #[test]
+    fn charwise_yanks_paste_after_the_cursor() {
+        assert_eq!(text(&keys("ab", "ylp")), "aab");
+        assert_eq!(text(&keys("ab\rc", "xjp")), "b\rca");
+    }
+
+    #[test]
+    fn counts_on_g_are_line_numbers() {
+        let editor = keys("a\rb\rc\rd", "3G");
+        assert_eq!(editor.index_in_text(), 4);
+        let editor = keys("a\rb\rc\rd", "G2gg");
+        assert_eq!(editor.index_in_text(), 2);
+        let editor = keys("a\rb\rc\rd", "9G");
+        assert_eq!(editor.index_in_text(), 6);
+        assert_eq!(keys("a\rb\rc\rd", "G").index_in_text(), 6);
+    }
+
+    #[test]
+    fn dg_and_dgg_delete_whole_lines() {
+        assert_eq!(text(&keys("a\rb", "dG")), "");
+        assert_eq!(text(&keys("a\rb\rc", "jdG")), "a");
+        assert_eq!(text(&keys("a\rb\rc", "jdgg")), "c");
+        assert_eq!(text(&keys("a\rbc\rd", "jlcGx")), "a\rx");
+    }
+
+    #[test]
+    fn huge_counts_are_clamped() {
+        let editor = keys("a\rb", "99999999999999999999j");
+        assert_eq!(editor.index_in_text(), 2);
+        assert_eq!(text(&keys("ab", "999999999x")), "");
+        assert_eq!(keys("a\rb", "99999999999999999999G").index_in_text(), 2);
+    }
+
+    #[test]
+    fn dot_repeats_the_last_change() {
+        assert_eq!(text(&keys("a b c d", "dw.")), "c d");
+        assert_eq!(text(&keys("abcdef", "x2.")), "def");
+        assert_eq!(text(&keys("a\rb\rc", "dd.")), "c");
+        let editor = keys("one two", &format!("cwx{ESCAPE}w."));
+        assert_eq!(text(&editor), "x x");
+    }
+
+    #[test]
+    fn a_command_undoes_as_one_step() {
+        let editor = keys("a\rb\rc", "2ddu");
+        assert_eq!(text(&editor), "a\rb\rc");
+        let editor = keys("one", &format!("Atwo three{ESCAPE}u"));
+        assert_eq!(text(&editor), "one");
+    }
+}